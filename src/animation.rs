@@ -0,0 +1,104 @@
+use std::time::Duration;
+
+use cgmath::{Matrix4, SquareMatrix, Vector2, Vector3};
+
+/// Fraction of the fling velocity lost per second is `1 - exp(-FRICTION)`.
+pub const FRICTION: f32 = 4.;
+
+/// Speed in pixels per second below which a fling stops.
+pub const MIN_SPEED: f32 = 20.;
+
+/// A camera change spread over time.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Motion {
+    /// Eased change of the view matrix, both ends being relative to the
+    /// camera origin `origin`.
+    Transition {
+        origin: Vector2<f64>,
+        from: Matrix4<f32>,
+        to: Matrix4<f32>,
+        duration: Duration,
+        elapsed: Duration,
+    },
+    /// Pan at `velocity` pixels per second, slowed down by friction.
+    Fling { velocity: Vector2<f32> },
+}
+
+/// Cubic ease in and out of `t` in `[0, 1]`.
+pub fn ease_in_out(t: f32) -> f32 {
+    let t = t.clamp(0., 1.);
+    if t < 0.5 {
+        4. * t * t * t
+    } else {
+        1. - (2. - 2. * t).powi(3) / 2.
+    }
+}
+
+/// View matrix at `t` of the way from `from` to `to`.
+///
+/// The change between them is treated as a similarity in view: the point it
+/// keeps in place stays there all along, so zooming around the cursor keeps
+/// the cursor still and a pure pan goes straight.
+pub fn interpolate(from: &Matrix4<f32>, to: &Matrix4<f32>, t: f32) -> Matrix4<f32> {
+    let delta = match from.invert() {
+        Some(inverse) => to * inverse,
+        None => return *to,
+    };
+
+    let scale = delta.x.x.hypot(delta.x.y);
+    let angle = delta.x.y.atan2(delta.x.x);
+    // Fixed point of the xy part, solving `(I - A) f = b`.
+    let (a, b, c, d) = (1. - delta.x.x, -delta.y.x, -delta.x.y, 1. - delta.y.y);
+    let det = a * d - b * c;
+    let xy = if det.abs() <= 1e-6 {
+        Matrix4::from_translation(Vector3::new(delta.w.x * t, delta.w.y * t, 0.))
+    } else {
+        let fx = (d * delta.w.x - b * delta.w.y) / det;
+        let fy = (a * delta.w.y - c * delta.w.x) / det;
+        Matrix4::from_translation(Vector3::new(fx, fy, 0.))
+            * Matrix4::from_angle_z(cgmath::Rad(angle * t))
+            * Matrix4::from_nonuniform_scale(scale.powf(t), scale.powf(t), 1.)
+            * Matrix4::from_translation(Vector3::new(-fx, -fy, 0.))
+    };
+    let z = if (1. - delta.z.z).abs() <= 1e-6 || delta.z.z <= 0. {
+        Matrix4::from_translation(Vector3::new(0., 0., delta.w.z * t))
+    } else {
+        let fz = delta.w.z / (1. - delta.z.z);
+        Matrix4::from_translation(Vector3::new(0., 0., fz))
+            * Matrix4::from_nonuniform_scale(1., 1., delta.z.z.powf(t))
+            * Matrix4::from_translation(Vector3::new(0., 0., -fz))
+    };
+    xy * z * from
+}
+
+#[cfg(test)]
+mod tests {
+    use cgmath::{assert_abs_diff_eq, EuclideanSpace, Point3, Transform};
+
+    use super::*;
+
+    #[test]
+    fn test_interpolate() {
+        let from = Matrix4::from_translation(Vector3::new(1., 2., -1.)) * Matrix4::from_scale(2.);
+        let pivot = Point3::new(0.5, -0.5, -1.);
+        let to = Matrix4::from_translation(pivot.to_vec())
+            * Matrix4::from_angle_z(cgmath::Deg(60.))
+            * Matrix4::from_scale(3.)
+            * Matrix4::from_translation(-pivot.to_vec())
+            * from;
+        assert_abs_diff_eq!(interpolate(&from, &to, 0.), from, epsilon = 1e-5);
+        assert_abs_diff_eq!(interpolate(&from, &to, 1.), to, epsilon = 1e-4);
+        let world = from.invert().unwrap().transform_point(pivot);
+        for t in [0.25, 0.5, 0.75] {
+            let pt = interpolate(&from, &to, t).transform_point(world);
+            assert_abs_diff_eq!(pt, pivot, epsilon = 1e-5);
+        }
+
+        let to = Matrix4::from_translation(Vector3::new(4., 0., 0.)) * from;
+        assert_abs_diff_eq!(
+            interpolate(&from, &to, 0.5),
+            Matrix4::from_translation(Vector3::new(2., 0., 0.)) * from,
+            epsilon = 1e-5
+        );
+    }
+}
@@ -1,4 +1,12 @@
-use cgmath::{InnerSpace, Point3, Transform, Vector3};
+use cgmath::{
+    EuclideanSpace, InnerSpace, MetricSpace, Point2, Point3, SquareMatrix, Transform, Vector2,
+    Vector3,
+};
+
+use serde::{Deserialize, Serialize};
+use winit::dpi::PhysicalSize;
+
+use crate::geometry::Rect;
 
 #[rustfmt::skip]
 const OPENGL_TO_WGPU_MATRIX: cgmath::Matrix4<f32> = cgmath::Matrix4::new(
@@ -8,36 +16,123 @@ const OPENGL_TO_WGPU_MATRIX: cgmath::Matrix4<f32> = cgmath::Matrix4::new(
     0.0, 0.0, 0.5, 1.0,
 );
 
+/// Distance, in visible heights, the view center may get from the local
+/// origin before the camera moves the origin there.
+const REBASE_DISTANCE: f32 = 256.;
+
 // Public
-pub fn untransform_point(m: &cgmath::Matrix4<f32>, pt: &Point3<f32>) -> Point3<f32> {
-    let origin = m.transform_point(Point3::new(0.0, 0.0, 0.0));
-    let v = pt - m.transform_point(Point3::new(0.0, 0.0, 0.0));
-    let vx = m.transform_point(Point3::new(1.0, 0.0, 0.0)) - origin;
-    let vy = m.transform_point(Point3::new(0.0, 1.0, 0.0)) - origin;
-    let vz = m.transform_point(Point3::new(0.0, 0.0, 1.0)) - origin;
-    let x = v.dot(vx) / vx.magnitude2();
-    let y = v.dot(vy) / vy.magnitude2();
-    let z = v.dot(vz) / vz.magnitude2();
-    Point3 { x, y, z }
+/// Normalized device coordinates of a pixel, y pointing down in pixels.
+pub fn px_to_ndc(px: Point2<f32>, size: PhysicalSize<u32>) -> Point2<f32> {
+    Point2::new(
+        2. * px.x / size.width as f32 - 1.,
+        1. - 2. * px.y / size.height as f32,
+    )
+}
+
+/// Inverse of [`px_to_ndc`].
+pub fn ndc_to_px(ndc: Point2<f32>, size: PhysicalSize<u32>) -> Point2<f32> {
+    Point2::new(
+        (ndc.x + 1.) / 2. * size.width as f32,
+        (1. - ndc.y) / 2. * size.height as f32,
+    )
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Projection {
+    #[default]
+    Perspective,
+    /// Parallel projection, seeing what the perspective sees at unit
+    /// distance whatever the depth.
+    Orthographic,
 }
 
-pub fn untransform_vector(m: &cgmath::Matrix4<f32>, v: &Vector3<f32>) -> Vector3<f32> {
-    let origin = m.transform_point(Point3::new(0.0, 0.0, 0.0));
-    let vx = m.transform_point(Point3::new(1.0, 0.0, 0.0)) - origin;
-    let vy = m.transform_point(Point3::new(0.0, 1.0, 0.0)) - origin;
-    let vz = m.transform_point(Point3::new(0.0, 0.0, 1.0)) - origin;
-    let x = v.dot(vx) / vx.magnitude2();
-    let y = v.dot(vy) / vy.magnitude2();
-    let z = v.dot(vz) / vz.magnitude2();
-    Vector3 { x, y, z }
+/// Where the view center may go on the world plane `z = 0`.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub enum PanBounds {
+    #[default]
+    Free,
+    /// The bounds of all strokes, grown by `margin` world units.
+    Content {
+        margin: f32,
+    },
+    Rect(Rect<f64>),
 }
 
+/// Constraints on the view, see [`Camera::zoom_level`] for the zoom.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Limits {
+    pub min_zoom: f32,
+    pub max_zoom: f32,
+    pub pan: PanBounds,
+}
+
+impl Limits {
+    /// The same limits made usable: a NaN zoom limit falls back to the
+    /// default one, the zoom range is ordered, a margin at least zero, and a
+    /// rect holding no point frees the pan.
+    pub fn normalized(&self) -> Self {
+        let default = Self::default();
+        let min_zoom = if self.min_zoom.is_nan() {
+            default.min_zoom
+        } else {
+            self.min_zoom
+        };
+        let max_zoom = if self.max_zoom.is_nan() {
+            default.max_zoom
+        } else {
+            self.max_zoom
+        };
+        let pan = match &self.pan {
+            PanBounds::Content { margin } => PanBounds::Content {
+                margin: margin.max(0.),
+            },
+            PanBounds::Rect(rect) if !rect.is_valid() => PanBounds::Free,
+            pan => pan.clone(),
+        };
+        Self {
+            min_zoom: min_zoom.min(max_zoom),
+            max_zoom: max_zoom.max(min_zoom),
+            pan,
+        }
+    }
+}
+
+impl Default for Limits {
+    fn default() -> Self {
+        Self {
+            min_zoom: 1e-4,
+            max_zoom: 1e4,
+            pan: PanBounds::Free,
+        }
+    }
+}
+
+/// What a camera looks at, without the aspect which follows the viewport.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct CameraState {
+    pub vm: [[f32; 4]; 4],
+    /// World position of the local origin `vm` starts from.
+    pub origin: [f64; 2],
+    pub fovy: f32,
+    pub znear: f32,
+    pub zfar: f32,
+    pub projection: Projection,
+}
+
+/// Far from the world origin, `f32` coordinates get too coarse. The camera
+/// thus works from a local origin kept close to what it looks at, held in
+/// `f64`: `vm` maps `world - origin` to view.
+#[derive(Clone)]
 pub struct Camera {
     aspect: f32,
     fovy: f32,
     znear: f32,
     zfar: f32,
-    pub vm: cgmath::Matrix4<f32>,
+    projection: Projection,
+    origin: Vector2<f64>,
+    vm: cgmath::Matrix4<f32>,
+    /// Inverse of `vm`, kept in sync by `set_vm`.
+    inverse: cgmath::Matrix4<f32>,
 }
 
 impl Camera {
@@ -50,23 +145,304 @@ impl Camera {
         znear: f32,
         zfar: f32,
     ) -> Self {
-        Self {
+        let mut camera = Self {
             aspect,
             fovy,
             znear,
             zfar,
-            vm: cgmath::Matrix4::look_at_rh(eye, target, up),
+            projection: Projection::default(),
+            origin: Vector2::new(0., 0.),
+            vm: cgmath::Matrix4::identity(),
+            inverse: cgmath::Matrix4::identity(),
+        };
+        camera.set_vm(cgmath::Matrix4::look_at_rh(eye, target, up));
+        camera
+    }
+
+    /// View matrix, from local coordinates to view.
+    pub fn vm(&self) -> &cgmath::Matrix4<f32> {
+        &self.vm
+    }
+
+    /// World position of the local origin.
+    pub fn origin(&self) -> Vector2<f64> {
+        self.origin
+    }
+
+    /// Move the local origin, keeping the view.
+    pub fn set_origin(&mut self, origin: Vector2<f64>) {
+        let delta = origin - self.origin;
+        let delta = Vector2::new(delta.x as f32, delta.y as f32);
+        self.origin = origin;
+        self.set_vm(self.vm * cgmath::Matrix4::from_translation(delta.extend(0.)));
+    }
+
+    /// Move the local origin to the view center when it got too far.
+    /// Returns whether it moved.
+    pub fn rebase(&mut self) -> bool {
+        let (center, top) = match (self.ndc_to_local(0., 0., 0.), self.ndc_to_local(0., 1., 0.)) {
+            (Some(center), Some(top)) => (center, top),
+            _ => return false,
+        };
+        let height = 2. * top.distance(center);
+        let offset = center.to_vec().truncate();
+        if offset.magnitude() <= REBASE_DISTANCE * height {
+            return false;
         }
+        self.set_origin(self.origin + Vector2::new(offset.x as f64, offset.y as f64));
+        true
+    }
+
+    /// Local coordinates of a world point.
+    pub fn to_local(&self, x: f64, y: f64) -> Point2<f32> {
+        Point2::new((x - self.origin.x) as f32, (y - self.origin.y) as f32)
+    }
+
+    fn to_world(&self, pt: Point3<f32>) -> Point3<f64> {
+        Point3::new(
+            pt.x as f64 + self.origin.x,
+            pt.y as f64 + self.origin.y,
+            pt.z as f64,
+        )
+    }
+
+    /// Replace the view matrix. A matrix without inverse is ignored.
+    pub fn set_vm(&mut self, vm: cgmath::Matrix4<f32>) {
+        match vm.invert() {
+            Some(inverse) => {
+                self.vm = vm;
+                self.inverse = inverse;
+            }
+            None => log::warn!("ignored a singular view matrix: {:?}", vm),
+        }
+    }
+
+    /// Apply `m`, a transform in view, after the view matrix.
+    pub fn transform(&mut self, m: cgmath::Matrix4<f32>) {
+        self.set_vm(m * self.vm);
+    }
+
+    /// Local position of a point in view, see [`Camera::origin`].
+    pub fn untransform_point(&self, pt: &Point3<f32>) -> Point3<f32> {
+        self.inverse.transform_point(*pt)
+    }
+
+    /// Local direction of a vector in view, see [`Camera::origin`].
+    pub fn untransform_vector(&self, v: &Vector3<f32>) -> Vector3<f32> {
+        self.inverse.transform_vector(*v)
     }
 
     pub fn set_aspect(&mut self, aspect: f32) {
         self.aspect = aspect;
     }
 
+    pub fn projection(&self) -> Projection {
+        self.projection
+    }
+
+    pub fn set_projection(&mut self, projection: Projection) {
+        self.projection = projection;
+    }
+
+    pub fn state(&self) -> CameraState {
+        CameraState {
+            vm: self.vm.into(),
+            origin: self.origin.into(),
+            fovy: self.fovy,
+            znear: self.znear,
+            zfar: self.zfar,
+            projection: self.projection,
+        }
+    }
+
+    pub fn set_state(&mut self, state: &CameraState) {
+        self.fovy = state.fovy;
+        self.znear = state.znear;
+        self.zfar = state.zfar;
+        self.projection = state.projection;
+        self.origin = state.origin.into();
+        self.set_vm(state.vm.into());
+    }
+
+    /// Rotate the content around the view z axis through `pivot`, a point in
+    /// view.
+    pub fn rotate(&mut self, angle: cgmath::Rad<f32>, pivot: Point3<f32>) {
+        let pivot = pivot.to_vec();
+        self.transform(
+            cgmath::Matrix4::from_translation(pivot)
+                * cgmath::Matrix4::from_angle_z(angle)
+                * cgmath::Matrix4::from_translation(-pivot),
+        );
+    }
+
+    /// Scale the content by `factor` around `pivot`, a point in view.
+    pub fn zoom(&mut self, factor: f32, pivot: Point3<f32>) {
+        let pivot = pivot.to_vec();
+        self.transform(
+            cgmath::Matrix4::from_translation(pivot)
+                * cgmath::Matrix4::from_scale(factor)
+                * cgmath::Matrix4::from_translation(-pivot),
+        );
+    }
+
+    /// Point in view of the world plane `z = 0` seen at the normalized device
+    /// coordinates `(x, y)`.
+    pub fn anchor(&self, x: f32, y: f32) -> Option<Point3<f32>> {
+        self.ndc_to_local(x, y, 0.)
+            .map(|pt| self.vm.transform_point(pt))
+    }
+
+    /// Move the content of the world plane `z = 0` by `delta` pixels.
+    pub fn pan(&mut self, delta: Vector2<f32>, size: PhysicalSize<u32>) {
+        let center = Point2::new(size.width as f32 / 2., size.height as f32 / 2.);
+        let (from, to) = (px_to_ndc(center, size), px_to_ndc(center + delta, size));
+        if let (Some(from), Some(to)) = (
+            self.ndc_to_local(from.x, from.y, 0.),
+            self.ndc_to_local(to.x, to.y, 0.),
+        ) {
+            let v = self.vm.transform_point(to) - self.vm.transform_point(from);
+            self.transform(cgmath::Matrix4::from_translation(v));
+        }
+    }
+
+    /// Look at `rect` of the world plane `z = 0` so that it fills the viewport,
+    /// keeping the rotation. The local origin moves to its center.
+    pub fn fit(&mut self, rect: &Rect<f64>) {
+        let angle = self.rotation();
+        let (sin, cos) = angle.0.sin_cos();
+        let (w, h) = (rect.width() as f32, rect.height() as f32);
+        // Size of the rect once rotated.
+        let width = (w * cos).abs() + (h * sin).abs();
+        let height = (w * sin).abs() + (h * cos).abs();
+        let top = (self.fovy / 2.).to_radians().tan();
+        let mut scale = (2. * top * self.aspect / width).min(2. * top / height);
+        if !scale.is_finite() {
+            scale = self.vm.x.truncate().magnitude();
+        }
+        self.origin = rect.center().to_vec();
+        self.set_vm(
+            cgmath::Matrix4::from_translation(-Vector3::unit_z())
+                * cgmath::Matrix4::from_angle_z(angle)
+                * cgmath::Matrix4::from_scale(scale),
+        );
+    }
+
+    /// How much larger the world plane `z = 0` looks than from the initial
+    /// camera at unit distance.
+    pub fn zoom_level(&self) -> Option<f32> {
+        Some(2. * (self.fovy / 2.).to_radians().tan() / self.visible_height()?)
+    }
+
+    /// World height of the viewport on the plane `z = 0`, along the view y
+    /// axis.
+    pub fn visible_height(&self) -> Option<f32> {
+        let top = self.ndc_to_local(0., 1., 0.)?;
+        let bottom = self.ndc_to_local(0., -1., 0.)?;
+        Some(top.distance(bottom))
+    }
+
+    /// Bring the zoom within the limits, around the view center, then the
+    /// view center within the pan bounds. `content` gives the bounds of the
+    /// strokes, only asked for when needed.
+    pub fn constrain(&mut self, limits: &Limits, content: impl FnOnce() -> Option<Rect<f64>>) {
+        let limits = limits.normalized();
+        if let (Some(level), Some(pivot)) = (self.zoom_level(), self.anchor(0., 0.)) {
+            let clamped = level.clamp(limits.min_zoom, limits.max_zoom);
+            if clamped != level {
+                self.zoom(clamped / level, pivot);
+            }
+        }
+        let bounds = match &limits.pan {
+            PanBounds::Free => None,
+            PanBounds::Content { margin } => content().map(|rect| rect.expand(*margin as f64)),
+            PanBounds::Rect(rect) => Some(*rect),
+        }
+        .filter(Rect::is_valid);
+        if let (Some(bounds), Some(center)) = (bounds, self.ndc_to_local(0., 0., 0.)) {
+            let min = self.to_local(bounds.min.x, bounds.min.y);
+            let max = self.to_local(bounds.max.x, bounds.max.y);
+            let clamped = Point3::new(
+                center.x.clamp(min.x, max.x),
+                center.y.clamp(min.y, max.y),
+                0.,
+            );
+            if clamped != center {
+                let v = self.vm.transform_point(center) - self.vm.transform_point(clamped);
+                self.transform(cgmath::Matrix4::from_translation(v));
+            }
+        }
+    }
+
+    /// Angle of the world x axis in view, in `(-pi, pi]`.
+    pub fn rotation(&self) -> cgmath::Rad<f32> {
+        let vx = self.vm.x;
+        cgmath::Rad(vx.y.atan2(vx.x))
+    }
+
     pub fn build_projection_matrix(&self) -> cgmath::Matrix4<f32> {
-        let proj = cgmath::perspective(cgmath::Deg(self.fovy), self.aspect, self.znear, self.zfar);
+        let proj = match self.projection {
+            Projection::Perspective => {
+                cgmath::perspective(cgmath::Deg(self.fovy), self.aspect, self.znear, self.zfar)
+            }
+            Projection::Orthographic => {
+                let top = (self.fovy / 2.).to_radians().tan();
+                let right = top * self.aspect;
+                cgmath::ortho(-right, right, -top, top, self.znear, self.zfar)
+            }
+        };
         OPENGL_TO_WGPU_MATRIX * proj
     }
+
+    /// Where the ray through the normalized device coordinates `(x, y)` meets
+    /// the world plane `z`.
+    pub fn ndc_to_world(&self, x: f32, y: f32, z: f32) -> Option<Point3<f64>> {
+        self.ndc_to_local(x, y, z).map(|pt| self.to_world(pt))
+    }
+
+    /// Like [`Camera::ndc_to_world`], in local coordinates.
+    pub fn ndc_to_local(&self, x: f32, y: f32, z: f32) -> Option<Point3<f32>> {
+        let inv = (self.build_projection_matrix() * self.vm).invert()?;
+        let near = inv.transform_point(Point3::new(x, y, 0.));
+        let far = inv.transform_point(Point3::new(x, y, 1.));
+        let dir = far - near;
+        if dir.z.abs() <= f32::EPSILON {
+            return None;
+        }
+        Some(near + dir * ((z - near.z) / dir.z))
+    }
+
+    /// Where the world point lands in normalized device coordinates.
+    pub fn world_to_ndc(&self, pt: Point3<f64>) -> Point2<f32> {
+        let local = self.to_local(pt.x, pt.y);
+        let ndc = (self.build_projection_matrix() * self.vm).transform_point(Point3::new(
+            local.x,
+            local.y,
+            pt.z as f32,
+        ));
+        Point2::new(ndc.x, ndc.y)
+    }
+
+    /// Point of the world plane `z = 0` under the pixel `px`.
+    pub fn screen_to_world(&self, px: Point2<f32>, size: PhysicalSize<u32>) -> Option<Point3<f64>> {
+        let ndc = px_to_ndc(px, size);
+        self.ndc_to_world(ndc.x, ndc.y, 0.)
+    }
+
+    /// Pixel where the world point lands.
+    pub fn world_to_screen(&self, pt: Point3<f64>, size: PhysicalSize<u32>) -> Point2<f32> {
+        ndc_to_px(self.world_to_ndc(pt), size)
+    }
+
+    /// Area of the world plane `z` seen through the viewport.
+    pub fn visible_rect(&self, z: f32) -> Option<Rect<f64>> {
+        [(-1., -1.), (1., -1.), (1., 1.), (-1., 1.)]
+            .into_iter()
+            .map(|(x, y)| {
+                self.ndc_to_world(x, y, z)
+                    .map(|pt| Rect::around(Point2::new(pt.x, pt.y), 0.))
+            })
+            .reduce(|a, b| Some(a?.union(&b?)))?
+    }
 }
 
 // We need this for Rust to store our data correctly for the shaders
@@ -79,9 +455,14 @@ pub struct CameraUniform {
     proj_view: [[f32; 4]; 4],
 }
 
+impl Default for CameraUniform {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl CameraUniform {
     pub fn new() -> Self {
-        use cgmath::SquareMatrix;
         Self {
             proj_view: cgmath::Matrix4::identity().into(),
         }
@@ -112,4 +493,359 @@ mod tests {
         let pos = camera.vm * cgmath::vec4(0., 1., 3., 1.);
         println!("{:?}", pos);
     }
+
+    #[test]
+    fn test_rotate() {
+        use cgmath::{assert_abs_diff_eq, Transform};
+
+        let mut camera = super::Camera::new(
+            (0.0, 0.0, 1.0).into(),
+            (0., 0., 0.).into(),
+            cgmath::Vector3::unit_y(),
+            1.,
+            90.0,
+            0.1,
+            100.0,
+        );
+        camera.transform(cgmath::Matrix4::from_scale(2.));
+        let pivot = cgmath::Point3::new(0.5, 0.5, -1.);
+        camera.rotate(cgmath::Deg(30.).into(), pivot);
+        assert_abs_diff_eq!(camera.rotation(), cgmath::Deg(30.).into(), epsilon = 1e-5);
+
+        let pt = cgmath::Point3::new(0.3, -0.2, -1.);
+        let o_pt = camera.untransform_point(&pt);
+        assert_abs_diff_eq!(camera.vm.transform_point(o_pt), pt, epsilon = 1e-5);
+
+        let o_pivot = camera.untransform_point(&pivot);
+        camera.rotate(-camera.rotation(), pivot);
+        assert_abs_diff_eq!(camera.rotation(), cgmath::Rad(0.), epsilon = 1e-5);
+        assert_abs_diff_eq!(camera.vm.transform_point(o_pivot), pivot, epsilon = 1e-5);
+    }
+
+    #[test]
+    fn test_anchor() {
+        use cgmath::{assert_abs_diff_eq, Transform};
+
+        let mut camera = super::Camera::new(
+            (0.0, 0.0, 1.0).into(),
+            (0., 0., 0.).into(),
+            cgmath::Vector3::unit_y(),
+            1.5,
+            90.0,
+            0.1,
+            100.0,
+        );
+        let ndc = |camera: &super::Camera, pt| {
+            (camera.build_projection_matrix() * camera.vm).transform_point(pt)
+        };
+        let (x, y) = (0.4, -0.7);
+        let local = camera.ndc_to_local(x, y, 0.).unwrap();
+        for _ in 0..3 {
+            let pivot = camera.anchor(x, y).unwrap();
+            camera.zoom(1.7, pivot);
+            camera.rotate(cgmath::Deg(25.).into(), pivot);
+        }
+        let o_ndc = ndc(&camera, local);
+        assert_abs_diff_eq!(o_ndc.x, x, epsilon = 1e-5);
+        assert_abs_diff_eq!(o_ndc.y, y, epsilon = 1e-5);
+    }
+
+    #[test]
+    fn test_orthographic() {
+        use cgmath::{assert_abs_diff_eq, Transform};
+
+        let mut camera = super::Camera::new(
+            (0.0, 0.0, 1.0).into(),
+            (0., 0., 0.).into(),
+            cgmath::Vector3::unit_y(),
+            2.,
+            90.0,
+            0.1,
+            100.0,
+        );
+        camera.set_projection(super::Projection::Orthographic);
+        let corner = camera.ndc_to_world(1., 1., 0.).unwrap();
+        assert_abs_diff_eq!(corner, cgmath::Point3::new(2., 1., 0.), epsilon = 1e-5);
+
+        // Depth does not change the size.
+        let proj_view = camera.build_projection_matrix() * camera.vm;
+        let near = proj_view.transform_point(cgmath::Point3::new(0.5, 0.5, 0.5));
+        let far = proj_view.transform_point(cgmath::Point3::new(0.5, 0.5, -5.));
+        assert_abs_diff_eq!(near.x, far.x, epsilon = 1e-5);
+        assert_abs_diff_eq!(near.y, far.y, epsilon = 1e-5);
+    }
+
+    #[test]
+    fn test_screen_round_trip() {
+        use cgmath::{assert_abs_diff_eq, Point2, Point3};
+
+        let size = winit::dpi::PhysicalSize::new(800, 600);
+        let mut camera = super::Camera::new(
+            (0.0, 0.0, 1.0).into(),
+            (0., 0., 0.).into(),
+            cgmath::Vector3::unit_y(),
+            800. / 600.,
+            90.0,
+            0.1,
+            100.0,
+        );
+        camera.transform(
+            cgmath::Matrix4::from_translation([0.2, -0.1, -0.5].into())
+                * cgmath::Matrix4::from_scale(3.),
+        );
+        camera.rotate(cgmath::Deg(40.).into(), Point3::new(0.1, 0.2, -1.));
+        for projection in [
+            super::Projection::Perspective,
+            super::Projection::Orthographic,
+        ] {
+            camera.set_projection(projection);
+            for px in [
+                Point2::new(0., 0.),
+                Point2::new(123., 456.),
+                Point2::new(800., 600.),
+            ] {
+                let pt = camera.screen_to_world(px, size).unwrap();
+                assert_abs_diff_eq!(pt.z, 0.);
+                assert_abs_diff_eq!(camera.world_to_screen(pt, size), px, epsilon = 1e-2);
+            }
+            let pt = Point3::new(-0.3, 0.4, 0.);
+            let px = camera.world_to_screen(pt, size);
+            assert_abs_diff_eq!(
+                camera.screen_to_world(px, size).unwrap(),
+                pt,
+                epsilon = 1e-4
+            );
+        }
+    }
+
+    #[derive(Clone, Debug)]
+    enum Op {
+        Move(f32, f32, f32),
+        Scale(f32, f32, f32),
+        Rotate(f32),
+    }
+
+    fn op() -> impl proptest::strategy::Strategy<Value = Op> {
+        use proptest::prelude::*;
+
+        prop_oneof![
+            (-5f32..5., -5f32..5., -5f32..5.).prop_map(|(x, y, z)| Op::Move(x, y, z)),
+            (0.2f32..5., 0.2f32..5., 0.2f32..5.).prop_map(|(x, y, z)| Op::Scale(x, y, z)),
+            (-3.2f32..3.2).prop_map(Op::Rotate),
+        ]
+    }
+
+    proptest::proptest! {
+        #[test]
+        fn test_untransform(
+            op_v in proptest::collection::vec(op(), 1..8),
+            pt in (-10f32..10., -10f32..10., -10f32..10.),
+        ) {
+            use cgmath::{InnerSpace, Transform};
+
+            let mut camera = super::Camera::new(
+                (0.0, 0.0, 1.0).into(),
+                (0., 0., 0.).into(),
+                cgmath::Vector3::unit_y(),
+                1.,
+                90.0,
+                0.1,
+                100.0,
+            );
+            for op in op_v {
+                camera.transform(match op {
+                    Op::Move(x, y, z) => cgmath::Matrix4::from_translation([x, y, z].into()),
+                    Op::Scale(x, y, z) => cgmath::Matrix4::from_nonuniform_scale(x, y, z),
+                    Op::Rotate(angle) => cgmath::Matrix4::from_angle_z(cgmath::Rad(angle)),
+                });
+            }
+            let pt = cgmath::Point3::from(pt);
+            let v = cgmath::Vector3::new(pt.x, pt.y, pt.z);
+            let o_pt = camera.vm().transform_point(camera.untransform_point(&pt));
+            let o_v = camera.vm().transform_vector(camera.untransform_vector(&v));
+            let tolerance = 1e-3 * (1. + v.magnitude());
+            proptest::prop_assert!((o_pt - pt).magnitude() <= tolerance, "{:?} != {:?}", o_pt, pt);
+            proptest::prop_assert!((o_v - v).magnitude() <= tolerance, "{:?} != {:?}", o_v, v);
+        }
+    }
+
+    #[test]
+    fn test_fit() {
+        use cgmath::{assert_abs_diff_eq, Point2, Point3};
+
+        let mut camera = super::Camera::new(
+            (0.0, 0.0, 1.0).into(),
+            (0., 0., 0.).into(),
+            cgmath::Vector3::unit_y(),
+            2.,
+            90.0,
+            0.1,
+            100.0,
+        );
+        let rect = crate::geometry::Rect::new(Point2::new(10., 20.), Point2::new(14., 30.));
+        camera.fit(&rect);
+        assert_abs_diff_eq!(
+            camera.world_to_ndc(Point3::new(12., 25., 0.)),
+            Point2::new(0., 0.)
+        );
+        assert_abs_diff_eq!(
+            camera.world_to_ndc(Point3::new(14., 30., 0.)),
+            Point2::new(0.2, 1.),
+            epsilon = 1e-5
+        );
+
+        camera.rotate(cgmath::Deg(90.).into(), Point3::new(0., 0., -1.));
+        camera.fit(&rect);
+        assert_abs_diff_eq!(camera.rotation(), cgmath::Deg(90.).into(), epsilon = 1e-5);
+        assert_abs_diff_eq!(
+            camera.world_to_ndc(Point3::new(14., 30., 0.)),
+            Point2::new(-1., 0.8),
+            epsilon = 1e-5
+        );
+    }
+
+    #[test]
+    fn test_constrain() {
+        use cgmath::{assert_abs_diff_eq, Point2, Point3};
+
+        let mut camera = super::Camera::new(
+            (0.0, 0.0, 1.0).into(),
+            (0., 0., 0.).into(),
+            cgmath::Vector3::unit_y(),
+            1.,
+            90.0,
+            0.1,
+            100.0,
+        );
+        assert_abs_diff_eq!(camera.zoom_level().unwrap(), 1., epsilon = 1e-5);
+        let limits = super::Limits {
+            min_zoom: 0.5,
+            max_zoom: 4.,
+            pan: super::PanBounds::Content { margin: 1. },
+        };
+        let content = crate::geometry::Rect::new(Point2::new(0., 0.), Point2::new(2., 2.));
+
+        camera.zoom(10., camera.anchor(0., 0.).unwrap());
+        camera.constrain(&limits, || Some(content));
+        assert_abs_diff_eq!(camera.zoom_level().unwrap(), 4., epsilon = 1e-4);
+
+        camera.transform(cgmath::Matrix4::from_translation([-20., 0., 0.].into()));
+        camera.constrain(&limits, || Some(content));
+        let center = camera.ndc_to_world(0., 0., 0.).unwrap();
+        assert_abs_diff_eq!(center, Point3::new(3., 0., 0.), epsilon = 1e-4);
+
+        // Unusable limits are made usable rather than panicking.
+        let limits = super::Limits {
+            min_zoom: 8.,
+            max_zoom: f32::NAN,
+            pan: super::PanBounds::Rect(crate::geometry::Rect::new(
+                Point2::new(1., 0.),
+                Point2::new(-1., 0.),
+            )),
+        };
+        let normalized = limits.normalized();
+        assert_eq!((normalized.min_zoom, normalized.max_zoom), (8., 1e4));
+        assert_eq!(normalized.pan, super::PanBounds::Free);
+        camera.constrain(&limits, || None);
+        assert_abs_diff_eq!(camera.zoom_level().unwrap(), 8., epsilon = 1e-3);
+        let limits = super::Limits {
+            min_zoom: 2.,
+            max_zoom: 1.,
+            pan: super::PanBounds::Content { margin: -5. },
+        };
+        camera.constrain(&limits, || Some(content));
+        assert_abs_diff_eq!(camera.zoom_level().unwrap(), 2., epsilon = 1e-3);
+        let center = camera.ndc_to_world(0., 0., 0.).unwrap();
+        assert_abs_diff_eq!(center, Point3::new(2., 0., 0.), epsilon = 1e-3);
+    }
+
+    #[test]
+    fn test_far_away() {
+        use cgmath::{assert_abs_diff_eq, Point2, Point3, Transform};
+
+        // What the shader does with a point of a line.
+        let ndc = |x0: f64, y0: f64| {
+            let mut camera = super::Camera::new(
+                (0.0, 0.0, 1.0).into(),
+                (0., 0., 0.).into(),
+                cgmath::Vector3::unit_y(),
+                1.,
+                90.0,
+                0.1,
+                100.0,
+            );
+            camera.fit(&crate::geometry::Rect::around(Point2::new(0., 0.), 0.005));
+            camera.set_state(&super::CameraState {
+                origin: [x0, y0],
+                ..camera.state()
+            });
+            assert!(!camera.rebase());
+            let offset = cgmath::Vector2::new(x0 + 0.001, y0 - 0.002) - camera.origin();
+            let pos = Point3::new(0.0003 + offset.x as f32, -0.0007 + offset.y as f32, 0.);
+            (camera.build_projection_matrix() * camera.vm).transform_point(pos)
+        };
+        assert_abs_diff_eq!(ndc(0., 0.), ndc(1e6, -1e6), epsilon = 1e-5);
+    }
+
+    #[test]
+    fn test_far_screen() {
+        use cgmath::{assert_abs_diff_eq, Point2};
+
+        let size = winit::dpi::PhysicalSize::new(800, 800);
+        let mut camera = super::Camera::new(
+            (0.0, 0.0, 1.0).into(),
+            (0., 0., 0.).into(),
+            cgmath::Vector3::unit_y(),
+            1.,
+            90.0,
+            0.1,
+            100.0,
+        );
+        camera.fit(&crate::geometry::Rect::around(Point2::new(0., 0.), 0.005));
+        camera.set_state(&super::CameraState {
+            origin: [1e7 + 0.001, -1e7],
+            ..camera.state()
+        });
+        let pt = camera
+            .screen_to_world(Point2::new(400., 400.), size)
+            .unwrap();
+        assert_abs_diff_eq!(pt.x, 1e7 + 0.001, epsilon = 1e-6);
+        assert_abs_diff_eq!(pt.y, -1e7, epsilon = 1e-6);
+        assert_abs_diff_eq!(
+            camera.world_to_screen(pt, size),
+            Point2::new(400., 400.),
+            epsilon = 1e-2
+        );
+    }
+
+    #[test]
+    fn test_rebase() {
+        use cgmath::{assert_abs_diff_eq, Point3};
+
+        let mut camera = super::Camera::new(
+            (0.0, 0.0, 1.0).into(),
+            (0., 0., 0.).into(),
+            cgmath::Vector3::unit_y(),
+            1.,
+            90.0,
+            0.1,
+            100.0,
+        );
+        camera.transform(cgmath::Matrix4::from_translation([0.5, 0., 0.].into()));
+        assert!(!camera.rebase());
+        camera.transform(cgmath::Matrix4::from_translation([-1e4, 0., 0.].into()));
+        let world = camera.ndc_to_world(0.5, 0.5, 0.).unwrap();
+        assert!(camera.rebase());
+        assert_abs_diff_eq!(camera.origin().x, 1e4 - 0.5, epsilon = 1e-2);
+        assert_abs_diff_eq!(
+            camera.ndc_to_world(0.5, 0.5, 0.).unwrap(),
+            world,
+            epsilon = 1e-2
+        );
+        assert_abs_diff_eq!(
+            camera.ndc_to_local(0., 0., 0.).unwrap(),
+            Point3::new(0., 0., 0.),
+            epsilon = 1e-2
+        );
+    }
 }
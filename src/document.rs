@@ -0,0 +1,280 @@
+use std::{cmp::Reverse, collections::BTreeMap};
+
+use cgmath::Point2;
+
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    camera::CameraState,
+    geometry::Rect,
+    index::SpatialIndex,
+    line::{Line, WidthMode},
+};
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Settings {
+    pub background: [f32; 4],
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Self {
+            background: [1., 1., 1., 1.],
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Ruling {
+    Plain,
+    Lined,
+    Ruled,
+    Graph,
+    Dotted,
+    /// Named as in Xournal++.
+    Other(String),
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Background {
+    pub color: [f32; 4],
+    pub ruling: Ruling,
+}
+
+impl Default for Background {
+    fn default() -> Self {
+        Self {
+            color: [1., 1., 1., 1.],
+            ruling: Ruling::Plain,
+        }
+    }
+}
+
+/// Printable area of the infinite canvas.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Page {
+    pub rect: Rect,
+    pub background: Background,
+}
+
+/// Identifies a line of a document.
+pub type StrokeId = u64;
+
+/// Everything drawn on a canvas, without any GPU state.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Document {
+    lines: BTreeMap<u64, Line>,
+    /// Ids from bottom to top.
+    order: std::vec::Vec<u64>,
+    next_id: u64,
+    /// Bounds of the lines.
+    index: SpatialIndex,
+    /// Largest width of the lines in screen pixels, which their bounds leave
+    /// out.
+    screen_width: f32,
+    pub settings: Settings,
+    pub pages: std::vec::Vec<Page>,
+    /// Saved views, by name.
+    pub bookmarks: BTreeMap<String, CameraState>,
+}
+
+impl Document {
+    pub fn new() -> Self {
+        Self {
+            lines: BTreeMap::new(),
+            order: std::vec::Vec::new(),
+            next_id: 1,
+            index: SpatialIndex::new(),
+            screen_width: 0.,
+            settings: Settings::default(),
+            pages: std::vec::Vec::new(),
+            bookmarks: BTreeMap::new(),
+        }
+    }
+
+    /// Id the next added line will get.
+    pub fn next_id(&self) -> u64 {
+        self.next_id
+    }
+
+    /// Add a line on top of all others.
+    pub fn push(&mut self, line: Line) -> u64 {
+        self.insert_at(self.order.len(), line)
+    }
+
+    /// Add a line at `index` in the z-order, 0 being the bottom.
+    pub fn insert_at(&mut self, index: usize, line: Line) -> u64 {
+        let id = self.next_id;
+        self.next_id += 1;
+        self.index(id, &line);
+        self.lines.insert(id, line);
+        self.order.insert(index.min(self.order.len()), id);
+        id
+    }
+
+    /// Add a line with a known id on top of all others.
+    ///
+    /// Any line already using `id` is replaced.
+    pub fn push_with_id(&mut self, id: u64, line: Line) {
+        self.insert_with_id(usize::MAX, id, line);
+    }
+
+    /// Add a line with a known id at `index` in the z-order, 0 being the
+    /// bottom.
+    ///
+    /// Any line already using `id` is replaced.
+    pub fn insert_with_id(&mut self, index: usize, id: u64, line: Line) {
+        self.remove(id);
+        self.index(id, &line);
+        self.lines.insert(id, line);
+        self.order.insert(index.min(self.order.len()), id);
+        self.next_id = self.next_id.max(id + 1);
+    }
+
+    pub fn remove(&mut self, id: u64) -> Option<Line> {
+        let line = self.lines.remove(&id)?;
+        self.order.retain(|o_id| *o_id != id);
+        self.index.remove(id);
+        if line.width_mode() == WidthMode::Screen && max_width(&line) >= self.screen_width {
+            self.screen_width = self
+                .lines
+                .values()
+                .filter(|line| line.width_mode() == WidthMode::Screen)
+                .map(max_width)
+                .fold(0., f32::max);
+        }
+        Some(line)
+    }
+
+    pub fn get(&self, id: u64) -> Option<&Line> {
+        self.lines.get(&id)
+    }
+
+    /// Position of a line in the z-order, 0 being the bottom.
+    pub fn index_of(&self, id: u64) -> Option<usize> {
+        self.order.iter().position(|o_id| *o_id == id)
+    }
+
+    /// Ids from bottom to top.
+    pub fn ids(&self) -> &[u64] {
+        &self.order
+    }
+
+    /// Lines from bottom to top.
+    pub fn iter(&self) -> impl Iterator<Item = (u64, &Line)> {
+        self.order.iter().map(|id| (*id, &self.lines[id]))
+    }
+
+    pub fn len(&self) -> usize {
+        self.order.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.order.is_empty()
+    }
+
+    /// Ids of the lines that may show in `rect`, for pixels of `px_size`
+    /// world units, in no particular order.
+    pub fn query(&self, rect: &Rect<f64>, px_size: f32) -> impl Iterator<Item = u64> + '_ {
+        self.index
+            .query(&rect.expand((self.screen_width * px_size) as f64))
+    }
+
+    /// Ids of the lines within `tolerance` of the world point `pos`, for
+    /// pixels of `px_size` world units, from top to bottom.
+    pub fn hit_test(
+        &self,
+        pos: Point2<f64>,
+        tolerance: f32,
+        px_size: f32,
+    ) -> std::vec::Vec<StrokeId> {
+        self.sweep_test(pos, pos, tolerance, px_size)
+    }
+
+    /// Like [`Document::hit_test`], for any point of the world segment
+    /// `[from, to]`.
+    pub fn sweep_test(
+        &self,
+        from: Point2<f64>,
+        to: Point2<f64>,
+        tolerance: f32,
+        px_size: f32,
+    ) -> std::vec::Vec<StrokeId> {
+        let rect = Rect::around(from, tolerance as f64).union(&Rect::around(to, tolerance as f64));
+        let mut ids: std::vec::Vec<_> = self
+            .query(&rect, px_size)
+            .filter(|id| {
+                self.lines[id]
+                    .sweep_distance(from, to, px_size)
+                    .is_some_and(|distance| distance <= tolerance)
+            })
+            .collect();
+        ids.sort_by_key(|id| Reverse(self.index_of(*id)));
+        ids
+    }
+
+    /// Area covered by all lines, widths included unless in screen pixels.
+    pub fn bounds(&self) -> Option<Rect<f64>> {
+        self.index.bounds()
+    }
+
+    /// Copy with screen widths turned into world widths, for pixels of
+    /// `px_size` world units.
+    pub fn resolve(&self, px_size: f32) -> Document {
+        let mut document = self.clone();
+        for (id, line) in document.lines.iter_mut() {
+            if line.width_mode() == WidthMode::Screen {
+                *line = line.resolve(px_size);
+                if let Some(rect) = line.bounds() {
+                    document.index.insert(*id, rect);
+                }
+            }
+        }
+        document.screen_width = 0.;
+        document
+    }
+
+    pub fn clear(&mut self) {
+        self.lines.clear();
+        self.order.clear();
+        self.index.clear();
+        self.screen_width = 0.;
+    }
+
+    fn index(&mut self, id: u64, line: &Line) {
+        if let Some(rect) = line.bounds() {
+            self.index.insert(id, rect);
+        }
+        if line.width_mode() == WidthMode::Screen {
+            self.screen_width = self.screen_width.max(max_width(line));
+        }
+    }
+}
+
+fn max_width(line: &Line) -> f32 {
+    line.points().iter().map(|pt| pt.width).fold(0., f32::max)
+}
+
+impl Default for Document {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use cgmath::Vector2;
+
+    use super::*;
+    use crate::line::test_line;
+
+    #[test]
+    fn test_hit_test_far() {
+        let mut document = Document::new();
+        let id = document.push(test_line(&[(0.04, 0.)], 0.015).with_origin(Vector2::new(1e6, 0.)));
+        let hit = |x: f64| document.hit_test(Point2::new(1e6 + x, 0.001), 0.001, 1.);
+        assert_eq!(hit(0.0301), [id]);
+        assert_eq!(hit(0.0499), [id]);
+        assert!(hit(0.02).is_empty());
+    }
+}
@@ -0,0 +1,167 @@
+use cgmath::Point2;
+
+use crate::{
+    document::{Document, StrokeId},
+    history::{Change, Step},
+};
+
+/// What the eraser removes.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum EraserMode {
+    /// Whole lines its circle touches.
+    #[default]
+    Stroke,
+    /// The parts of the lines under its circle, splitting them.
+    Partial,
+}
+
+/// A drag of the eraser along a path, its circle sweeping over the lines.
+#[derive(Clone, Debug)]
+pub struct Eraser {
+    mode: EraserMode,
+    /// Radius in pixels.
+    radius: f32,
+    /// World position of the end of the path so far.
+    last: Point2<f64>,
+    change: Change,
+}
+
+impl Eraser {
+    /// Start at the world position `pos`, erasing nothing yet.
+    pub fn new(pos: Point2<f64>, radius: f32, mode: EraserMode) -> Self {
+        Self {
+            mode,
+            radius,
+            last: pos,
+            change: Change::new(),
+        }
+    }
+
+    pub fn mode(&self) -> EraserMode {
+        self.mode
+    }
+
+    pub fn radius(&self) -> f32 {
+        self.radius
+    }
+
+    /// Erase along the path to the world position `pos`, for pixels of
+    /// `px_size` world units. Returns the ids of the removed and added lines.
+    pub fn move_to(
+        &mut self,
+        document: &mut Document,
+        pos: Point2<f64>,
+        px_size: f32,
+    ) -> std::vec::Vec<StrokeId> {
+        let radius = self.radius * px_size;
+        let hits = document.sweep_test(self.last, pos, radius, px_size);
+        let mut ids = std::vec::Vec::new();
+        for id in hits {
+            let pieces = match self.mode {
+                EraserMode::Stroke => std::vec::Vec::new(),
+                EraserMode::Partial => match document
+                    .get(id)
+                    .and_then(|line| line.erase(self.last, pos, radius, px_size))
+                {
+                    Some(pieces) => pieces,
+                    None => continue,
+                },
+            };
+            let index = match document.index_of(id) {
+                Some(index) => index,
+                None => continue,
+            };
+            self.change.extend(Step::remove(document, id));
+            ids.push(id);
+            for (i, piece) in pieces.into_iter().enumerate() {
+                let step = Step::insert(document, index + i, piece);
+                ids.push(step.id());
+                self.change.push(step);
+            }
+        }
+        self.last = pos;
+        ids
+    }
+
+    /// Everything erased, as one change.
+    pub fn finish(self) -> Change {
+        self.change
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use cgmath::assert_abs_diff_eq;
+
+    use super::*;
+    use crate::{line::Line, point::Point};
+
+    #[test]
+    fn test_erase() {
+        let mut document = Document::new();
+        for y in [0., 10., 20.] {
+            document.push(Line::from_points(
+                [0., 5.]
+                    .into_iter()
+                    .map(|x| Point {
+                        pos: [x, y, 0.].into(),
+                        color: [0., 0., 0., 1.],
+                        width: 1.,
+                    })
+                    .collect(),
+            ));
+        }
+        let mut eraser = Eraser::new(Point2::new(-3., -5.), 1.5, EraserMode::Stroke);
+        assert!(eraser
+            .move_to(&mut document, Point2::new(-3., 15.), 1.)
+            .is_empty());
+        assert_eq!(eraser.move_to(&mut document, Point2::new(8., 11.), 1.), [2]);
+        assert_eq!(eraser.move_to(&mut document, Point2::new(2., -2.), 1.), [1]);
+        assert_eq!(document.ids(), &[3]);
+        assert_eq!(eraser.finish().len(), 2);
+    }
+
+    #[test]
+    fn test_erase_partial() {
+        let mut document = Document::new();
+        document.push(Line::from_points(
+            [(0., 1.), (10., 3.), (20., 5.)]
+                .into_iter()
+                .map(|(x, width)| Point {
+                    pos: [x, 0., 0.].into(),
+                    color: [x / 10., 0., 0., 1.],
+                    width,
+                })
+                .collect(),
+        ));
+        let mut eraser = Eraser::new(Point2::new(5., 8.), 1., EraserMode::Partial);
+        assert!(eraser
+            .move_to(&mut document, Point2::new(5., 5.), 1.)
+            .is_empty());
+        assert_eq!(
+            eraser.move_to(&mut document, Point2::new(5., -2.), 1.),
+            [1, 2, 3]
+        );
+        let pieces: std::vec::Vec<_> = document.iter().map(|(_, line)| line.points()).collect();
+        assert_eq!(pieces.len(), 2);
+        // Cut where the outline, `1 + x / 5` wide, leaves the eraser.
+        assert_eq!(pieces[0].len(), 2);
+        assert_abs_diff_eq!(pieces[0][1].pos, [2.5, 0., 0.].into(), epsilon = 1e-4);
+        assert_abs_diff_eq!(pieces[0][1].width, 1.5, epsilon = 1e-4);
+        assert_abs_diff_eq!(pieces[0][1].color[0], 0.25, epsilon = 1e-4);
+        assert_eq!(pieces[1].len(), 3);
+        assert_abs_diff_eq!(pieces[1][0].pos, [8.75, 0., 0.].into(), epsilon = 1e-4);
+
+        // Points inside the eraser go with it.
+        let mut eraser = Eraser::new(Point2::new(6.5, 0.), 1., EraserMode::Partial);
+        assert_eq!(
+            eraser.move_to(&mut document, Point2::new(6.5, 0.), 1.),
+            [3, 4]
+        );
+        let pieces: std::vec::Vec<_> = document.iter().map(|(_, line)| line.points()).collect();
+        assert_eq!(pieces.len(), 2);
+        assert_eq!(pieces[1].len(), 2);
+        assert_abs_diff_eq!(pieces[1][0].pos, [10.625, 0., 0.].into(), epsilon = 1e-4);
+        assert_eq!(eraser.finish().len(), 2);
+    }
+}
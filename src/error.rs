@@ -0,0 +1,9 @@
+use std::io::{self, Error};
+
+/// Error of a malformed input, from a file to an import.
+pub(crate) fn invalid_data<E>(e: E) -> Error
+where
+    E: Into<Box<dyn std::error::Error + Send + Sync>>,
+{
+    Error::new(io::ErrorKind::InvalidData, e)
+}
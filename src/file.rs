@@ -0,0 +1,376 @@
+use std::{
+    collections::BTreeMap,
+    io::{self, Error},
+};
+
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::{
+    camera::CameraState,
+    document::{Background, Document, Page, Settings},
+    error::invalid_data,
+    line::{Line, Sample, WidthMode},
+    point::Point,
+};
+
+/// Upgrades the raw content of a file from one version to the next.
+pub type Migration = fn(Value) -> io::Result<Value>;
+
+/// `MIGRATIONS[i]` turns a file of version `i + 1` into one of version `i + 2`.
+///
+/// When the model changes, append the function that rewrites the previous
+/// layout; `VERSION` follows automatically.
+pub const MIGRATIONS: &[Migration] = &[
+    add_pages,
+    add_samples,
+    add_page_backgrounds,
+    add_bookmarks,
+    add_origins,
+    add_width_modes,
+    add_camera_states,
+];
+
+pub const VERSION: u32 = MIGRATIONS.len() as u32 + 1;
+
+/// Leading bytes of the binary encoding, followed by the CBOR payload.
+const MAGIC: &[u8; 4] = b"PNTG";
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Format {
+    Json,
+    Binary,
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Stroke {
+    pub id: u64,
+    /// World position the point positions are relative to.
+    pub origin: [f64; 2],
+    pub width_mode: WidthMode,
+    pub points: std::vec::Vec<Point>,
+    /// One per point, or empty.
+    pub samples: std::vec::Vec<Sample>,
+}
+
+/// The native file content.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct NativeFile {
+    pub version: u32,
+    pub camera: CameraState,
+    pub settings: Settings,
+    pub pages: std::vec::Vec<Page>,
+    pub bookmarks: BTreeMap<String, CameraState>,
+    /// Strokes from bottom to top.
+    pub strokes: std::vec::Vec<Stroke>,
+}
+
+impl NativeFile {
+    pub fn new(document: &Document, camera: &CameraState) -> Self {
+        Self {
+            version: VERSION,
+            camera: camera.clone(),
+            settings: document.settings.clone(),
+            pages: document.pages.clone(),
+            bookmarks: document.bookmarks.clone(),
+            strokes: document
+                .iter()
+                .map(|(id, line)| Stroke {
+                    id,
+                    origin: line.origin().into(),
+                    width_mode: line.width_mode(),
+                    points: line.points().to_vec(),
+                    samples: line.samples().to_vec(),
+                })
+                .collect(),
+        }
+    }
+
+    pub fn into_document(self) -> (Document, CameraState) {
+        let mut document = Document::new();
+        document.settings = self.settings;
+        document.pages = self.pages;
+        document.bookmarks = self.bookmarks;
+        for stroke in self.strokes {
+            if stroke.points.is_empty() {
+                log::warn!("skipped the stroke {} without points", stroke.id);
+                continue;
+            }
+            document.push_with_id(
+                stroke.id,
+                Line::from_samples(stroke.points, stroke.samples)
+                    .with_origin(stroke.origin.into())
+                    .with_width_mode(stroke.width_mode),
+            );
+        }
+        (document, self.camera)
+    }
+
+    pub fn encode(&self, format: Format) -> io::Result<std::vec::Vec<u8>> {
+        match format {
+            Format::Json => serde_json::to_vec(self).map_err(Error::other),
+            Format::Binary => {
+                let mut data = MAGIC.to_vec();
+                ciborium::ser::into_writer(self, &mut data).map_err(Error::other)?;
+                Ok(data)
+            }
+        }
+    }
+
+    /// Decode either encoding, migrating older versions.
+    pub fn decode(data: &[u8]) -> io::Result<Self> {
+        let value: Value = match data.strip_prefix(MAGIC) {
+            Some(payload) => ciborium::de::from_reader(payload).map_err(invalid_data)?,
+            None => serde_json::from_slice(data).map_err(invalid_data)?,
+        };
+        serde_json::from_value(migrate(value)?).map_err(invalid_data)
+    }
+}
+
+fn migrate(mut value: Value) -> io::Result<Value> {
+    let version = value
+        .get("version")
+        .and_then(Value::as_u64)
+        .ok_or_else(|| invalid_data("missing version"))?;
+    if version == 0 || version > VERSION as u64 {
+        return Err(invalid_data(format!("unsupported version {}", version)));
+    }
+    for migration in &MIGRATIONS[(version - 1) as usize..] {
+        value = migration(value)?;
+    }
+    value["version"] = VERSION.into();
+    Ok(value)
+}
+
+/// Version 2 added pages.
+fn add_pages(mut value: Value) -> io::Result<Value> {
+    value["pages"] = Value::Array(std::vec::Vec::new());
+    Ok(value)
+}
+
+/// Version 3 added pen samples to strokes.
+fn add_samples(mut value: Value) -> io::Result<Value> {
+    if let Some(strokes) = value["strokes"].as_array_mut() {
+        for stroke in strokes {
+            object(stroke, "stroke")?.insert("samples".into(), Value::Array(std::vec::Vec::new()));
+        }
+    }
+    Ok(value)
+}
+
+/// Version 4 added page backgrounds.
+fn add_page_backgrounds(mut value: Value) -> io::Result<Value> {
+    if let Some(pages) = value["pages"].as_array_mut() {
+        for page in pages {
+            object(page, "page")?.insert(
+                "background".into(),
+                serde_json::to_value(Background::default())?,
+            );
+        }
+    }
+    Ok(value)
+}
+
+/// Version 5 added camera bookmarks.
+fn add_bookmarks(mut value: Value) -> io::Result<Value> {
+    value["bookmarks"] = Value::Object(serde_json::Map::new());
+    Ok(value)
+}
+
+/// Version 6 made positions relative to origins, for precision far away.
+fn add_origins(mut value: Value) -> io::Result<Value> {
+    value["view_origin"] = serde_json::json!([0., 0.]);
+    if let Some(strokes) = value["strokes"].as_array_mut() {
+        for stroke in strokes {
+            object(stroke, "stroke")?.insert("origin".into(), serde_json::json!([0., 0.]));
+        }
+    }
+    if let Some(bookmarks) = value["bookmarks"].as_object_mut() {
+        for bookmark in bookmarks.values_mut() {
+            object(bookmark, "bookmark")?.insert("origin".into(), serde_json::json!([0., 0.]));
+        }
+    }
+    Ok(value)
+}
+
+/// Version 7 added screen widths.
+fn add_width_modes(mut value: Value) -> io::Result<Value> {
+    if let Some(strokes) = value["strokes"].as_array_mut() {
+        for stroke in strokes {
+            object(stroke, "stroke")?.insert("width_mode".into(), serde_json::json!("World"));
+        }
+    }
+    Ok(value)
+}
+
+/// Version 8 saved the whole camera rather than its view matrix, older files
+/// getting the projection of a new canvas.
+fn add_camera_states(mut value: Value) -> io::Result<Value> {
+    let file = object(&mut value, "file")?;
+    let vm = file.remove("view").unwrap_or_default();
+    let origin = file.remove("view_origin").unwrap_or_default();
+    file.insert(
+        "camera".into(),
+        serde_json::json!({
+            "vm": vm,
+            "origin": origin,
+            "fovy": 90.,
+            "znear": 0.1,
+            "zfar": 100.,
+            "projection": "Perspective",
+        }),
+    );
+    Ok(value)
+}
+
+/// `value` as an object, `what` naming it in the error otherwise.
+fn object<'a>(
+    value: &'a mut Value,
+    what: &str,
+) -> io::Result<&'a mut serde_json::Map<String, Value>> {
+    value
+        .as_object_mut()
+        .ok_or_else(|| invalid_data(format!("{} is not an object", what)))
+}
+
+#[cfg(test)]
+mod tests {
+    use cgmath::SquareMatrix;
+
+    use super::*;
+    use crate::{camera::Projection, document::Ruling, geometry::Rect};
+
+    fn camera() -> CameraState {
+        CameraState {
+            vm: cgmath::Matrix4::identity().into(),
+            origin: [0., 0.],
+            fovy: 90.,
+            znear: 0.1,
+            zfar: 100.,
+            projection: Projection::Perspective,
+        }
+    }
+
+    fn document() -> Document {
+        let mut document = Document::new();
+        document.settings.background = [0., 0., 0., 1.];
+        document.pages.push(Page {
+            rect: Rect::new((0., 0.).into(), (2., 3.).into()),
+            background: Background {
+                color: [1., 1., 0.8, 1.],
+                ruling: Ruling::Other("staves".to_string()),
+            },
+        });
+        let mut line = Line::new(Point {
+            pos: [0., 0., 0.].into(),
+            color: [1., 0., 0., 1.],
+            width: 0.1,
+        });
+        line.push_point(Point {
+            pos: [1., 2., 0.].into(),
+            color: [0., 1., 0., 0.5],
+            width: 0.2,
+        });
+        document.push(
+            line.clone()
+                .with_origin(cgmath::Vector2::new(1e6 + 0.5, 0.25)),
+        );
+        let mut line = Line::with_sample(line.points()[0].clone(), Sample::default());
+        line.push_sample(
+            line.points()[0].clone(),
+            Sample {
+                time: 16.,
+                force: 0.5,
+            },
+        );
+        document.insert_at(0, line.clone());
+        document.push(line.with_width_mode(WidthMode::Screen));
+        document.bookmarks.insert(
+            "intro".to_string(),
+            CameraState {
+                vm: cgmath::Matrix4::from_scale(2.).into(),
+                origin: [1e6, -2e6],
+                fovy: 90.,
+                znear: 0.1,
+                zfar: 100.,
+                projection: Projection::Orthographic,
+            },
+        );
+        document
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let document = document();
+        let camera = CameraState {
+            vm: cgmath::Matrix4::from_translation([1., 2., 3.].into()).into(),
+            origin: [-3e7, 0.125],
+            fovy: 60.,
+            znear: 0.5,
+            zfar: 50.,
+            projection: Projection::Orthographic,
+        };
+        for format in [Format::Json, Format::Binary] {
+            let data = NativeFile::new(&document, &camera).encode(format).unwrap();
+            let (o_document, o_camera) = NativeFile::decode(&data).unwrap().into_document();
+            assert_eq!(o_document.ids(), &[2, 1, 3]);
+            assert_eq!(o_document, document);
+            assert_eq!(o_camera, camera);
+        }
+    }
+
+    #[test]
+    fn test_migrate_v1() {
+        let data = br#"{
+            "version": 1,
+            "view": [[1, 0, 0, 0], [0, 1, 0, 0], [0, 0, 1, 0], [0, 0, 0, 1]],
+            "settings": { "background": [1, 1, 1, 1] },
+            "strokes": [{
+                "id": 3,
+                "points": [{ "pos": { "x": 1, "y": 2, "z": 0 }, "color": [0, 0, 0, 1], "width": 1 }]
+            }]
+        }"#;
+        let file = NativeFile::decode(data).unwrap();
+        assert_eq!(file.version, VERSION);
+        assert!(file.pages.is_empty());
+        assert!(file.bookmarks.is_empty());
+        assert_eq!(file.camera, camera());
+        assert_eq!(file.into_document().0.ids(), &[3]);
+    }
+
+    #[test]
+    fn test_migrate_malformed() {
+        for data in [
+            &br#"{"version": 1, "strokes": [1]}"#[..],
+            br#"{"version": 3, "pages": [null]}"#,
+            br#"{"version": 5, "strokes": [], "bookmarks": {"intro": []}}"#,
+            br#"{"version": 6, "strokes": ["stroke"]}"#,
+        ] {
+            assert_eq!(
+                NativeFile::decode(data).unwrap_err().kind(),
+                io::ErrorKind::InvalidData
+            );
+        }
+    }
+
+    #[test]
+    fn test_empty_stroke() {
+        let mut file = NativeFile::new(&document(), &camera());
+        let ids: std::vec::Vec<_> = file.strokes.iter().map(|stroke| stroke.id).collect();
+        file.strokes[0].points.clear();
+        let data = file.encode(Format::Json).unwrap();
+        let (document, _) = NativeFile::decode(&data).unwrap().into_document();
+        assert_eq!(document.ids(), &ids[1..]);
+    }
+
+    #[test]
+    fn test_newer_version() {
+        let mut file = NativeFile::new(&document(), &camera());
+        file.version = VERSION + 1;
+        let data = file.encode(Format::Json).unwrap();
+        assert_eq!(
+            NativeFile::decode(&data).unwrap_err().kind(),
+            io::ErrorKind::InvalidData
+        );
+    }
+}
@@ -0,0 +1,308 @@
+use cgmath::{BaseFloat, EuclideanSpace, InnerSpace, MetricSpace, Point2, Vector2};
+use serde::{Deserialize, Serialize};
+
+/// Iterations of the searches along a segment, each keeping at most two
+/// thirds of the range.
+const SEARCH_STEPS: usize = 40;
+
+/// Axis aligned rectangle in the xy plane. World areas that must stay exact
+/// far from the world origin are `Rect<f64>`.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Rect<S = f32> {
+    pub min: Point2<S>,
+    pub max: Point2<S>,
+}
+
+impl<S: BaseFloat> Rect<S> {
+    pub fn new(min: Point2<S>, max: Point2<S>) -> Self {
+        Self { min, max }
+    }
+
+    /// Square of half side `radius` around `center`.
+    pub fn around(center: Point2<S>, radius: S) -> Self {
+        Self {
+            min: Point2::new(center.x - radius, center.y - radius),
+            max: Point2::new(center.x + radius, center.y + radius),
+        }
+    }
+
+    pub fn width(&self) -> S {
+        self.max.x - self.min.x
+    }
+
+    pub fn height(&self) -> S {
+        self.max.y - self.min.y
+    }
+
+    pub fn center(&self) -> Point2<S> {
+        self.min.midpoint(self.max)
+    }
+
+    /// Whether the rectangle holds any point, which NaN coordinates rule
+    /// out.
+    pub fn is_valid(&self) -> bool {
+        self.min.x <= self.max.x && self.min.y <= self.max.y
+    }
+
+    pub fn union(&self, other: &Self) -> Self {
+        Self {
+            min: Point2::new(self.min.x.min(other.min.x), self.min.y.min(other.min.y)),
+            max: Point2::new(self.max.x.max(other.max.x), self.max.y.max(other.max.y)),
+        }
+    }
+
+    pub fn intersects(&self, other: &Self) -> bool {
+        self.min.x <= other.max.x
+            && other.min.x <= self.max.x
+            && self.min.y <= other.max.y
+            && other.min.y <= self.max.y
+    }
+
+    pub fn expand(&self, margin: S) -> Self {
+        Self {
+            min: Point2::new(self.min.x - margin, self.min.y - margin),
+            max: Point2::new(self.max.x + margin, self.max.y + margin),
+        }
+    }
+
+    /// The same rectangle with coordinates of another type, rounded if need
+    /// be.
+    pub fn cast<T: BaseFloat>(&self) -> Rect<T> {
+        let cast = |pt: Point2<S>| Point2::new(T::from(pt.x).unwrap(), T::from(pt.y).unwrap());
+        Rect {
+            min: cast(self.min),
+            max: cast(self.max),
+        }
+    }
+}
+
+/// Signed distance from `pt` to the convex hull of the circles `(c0, r0)` and
+/// `(c1, r1)`, negative inside.
+pub fn capsule_distance(
+    pt: Point2<f32>,
+    c0: Point2<f32>,
+    r0: f32,
+    c1: Point2<f32>,
+    r1: f32,
+) -> f32 {
+    let axis = c1 - c0;
+    let length = axis.magnitude();
+    let dr = r1 - r0;
+    if length <= dr.abs() {
+        // One circle contains the other.
+        return if r0 > r1 {
+            pt.distance(c0) - r0
+        } else {
+            pt.distance(c1) - r1
+        };
+    }
+    let u = axis / length;
+    let q = pt - c0;
+    let x = q.dot(u);
+    let y = (q.x * u.y - q.y * u.x).abs();
+    // Distance to the circle at `t` along the axis, convex in `t`, is the
+    // smallest where its slope is 0.
+    let s = dr / length;
+    let t = (x + s * y / (1. - s * s).sqrt()).clamp(0., length);
+    (x - t).hypot(y) - r0 - s * t
+}
+
+/// Smallest [`capsule_distance`] from the points of the segment `[a, b]`.
+pub fn sweep_distance(
+    a: Point2<f32>,
+    b: Point2<f32>,
+    c0: Point2<f32>,
+    r0: f32,
+    c1: Point2<f32>,
+    r1: f32,
+) -> f32 {
+    let at = |s: f32| capsule_distance(a + (b - a) * s, c0, r0, c1, r1);
+    if a == b {
+        return at(0.);
+    }
+    // The distance to a convex shape is convex along the segment.
+    let (mut lo, mut hi) = (0f32, 1f32);
+    for _ in 0..SEARCH_STEPS {
+        let m0 = lo + (hi - lo) / 3.;
+        let m1 = hi - (hi - lo) / 3.;
+        if at(m0) <= at(m1) {
+            hi = m1;
+        } else {
+            lo = m0;
+        }
+    }
+    at((lo + hi) / 2.)
+}
+
+/// Range of `t` in `[0, 1]` where `p0 + (p1 - p0) * t` is within `radius` of
+/// the segment `[a, b]`, `None` when the segments stay farther apart.
+pub fn segment_in_capsule(
+    p0: Point2<f32>,
+    p1: Point2<f32>,
+    a: Point2<f32>,
+    b: Point2<f32>,
+    radius: f32,
+) -> Option<(f32, f32)> {
+    let d = p1 - p0;
+    // The capsule is convex, so the union of the ranges in its parts is a
+    // range too.
+    let mut range: Option<(f32, f32)> = None;
+    let mut add = |lo: f32, hi: f32| {
+        if lo <= hi {
+            range = Some(range.map_or((lo, hi), |(l, h)| (l.min(lo), h.max(hi))));
+        }
+    };
+    for center in [a, b] {
+        if let Some((lo, hi)) = circle_range(p0, d, center, radius) {
+            add(lo, hi);
+        }
+    }
+    let axis = b - a;
+    let length = axis.magnitude();
+    if length > 0. {
+        let u = axis / length;
+        let n = Vector2::new(-u.y, u.x);
+        let q = p0 - a;
+        let (mut lo, mut hi) = (f32::NEG_INFINITY, f32::INFINITY);
+        for (start, slope, min, max) in [
+            (q.dot(u), d.dot(u), 0., length),
+            (q.dot(n), d.dot(n), -radius, radius),
+        ] {
+            if slope.abs() <= f32::EPSILON {
+                if start < min || start > max {
+                    lo = f32::INFINITY;
+                }
+            } else {
+                let (t0, t1) = ((min - start) / slope, (max - start) / slope);
+                lo = lo.max(t0.min(t1));
+                hi = hi.min(t0.max(t1));
+            }
+        }
+        add(lo, hi);
+    }
+    let (lo, hi) = range?;
+    let (lo, hi) = (lo.max(0.), hi.min(1.));
+    (lo <= hi).then_some((lo, hi))
+}
+
+/// Like [`segment_in_capsule`], the points of `[p0, p1]` being circles whose
+/// radius goes from `r0` to `r1`, as along a stroke: the range of `t` where
+/// the circle at `t` comes within `radius` of the segment `[a, b]`.
+pub fn tapered_segment_in_capsule(
+    p0: Point2<f32>,
+    p1: Point2<f32>,
+    r0: f32,
+    r1: f32,
+    a: Point2<f32>,
+    b: Point2<f32>,
+    radius: f32,
+) -> Option<(f32, f32)> {
+    if r0 == r1 {
+        return segment_in_capsule(p0, p1, a, b, radius + r0);
+    }
+    // The distance to the segment is convex along `[p0, p1]`, and so is this
+    // gap as the radius changes linearly.
+    let gap =
+        |t: f32| capsule_distance(p0 + (p1 - p0) * t, a, 0., b, 0.) - radius - r0 - (r1 - r0) * t;
+    let (mut lo, mut hi) = (0f32, 1f32);
+    for _ in 0..SEARCH_STEPS {
+        let m0 = lo + (hi - lo) / 3.;
+        let m1 = hi - (hi - lo) / 3.;
+        if gap(m0) <= gap(m1) {
+            hi = m1;
+        } else {
+            lo = m0;
+        }
+    }
+    let closest = (lo + hi) / 2.;
+    if gap(closest) > 0. {
+        return None;
+    }
+    // Where the gap crosses 0, between `inside` and `outside`.
+    let border = |mut inside: f32, mut outside: f32| {
+        if gap(outside) <= 0. {
+            return outside;
+        }
+        for _ in 0..SEARCH_STEPS {
+            let middle = (inside + outside) / 2.;
+            if gap(middle) <= 0. {
+                inside = middle;
+            } else {
+                outside = middle;
+            }
+        }
+        inside
+    };
+    Some((border(closest, 0.), border(closest, 1.)))
+}
+
+/// Range of `t` where `p0 + d * t` is within `radius` of `center`.
+fn circle_range(
+    p0: Point2<f32>,
+    d: Vector2<f32>,
+    center: Point2<f32>,
+    radius: f32,
+) -> Option<(f32, f32)> {
+    let f = p0 - center;
+    let a = d.dot(d);
+    let b = f.dot(d);
+    let c = f.dot(f) - radius * radius;
+    if a <= f32::EPSILON {
+        return (c <= 0.).then_some((f32::NEG_INFINITY, f32::INFINITY));
+    }
+    let discriminant = b * b - a * c;
+    if discriminant < 0. {
+        return None;
+    }
+    let root = discriminant.sqrt();
+    Some(((-b - root) / a, (-b + root) / a))
+}
+
+/// Whether `pt` is inside the closed polygon `polygon`, by the even-odd rule.
+pub fn point_in_polygon(pt: Point2<f32>, polygon: &[Point2<f32>]) -> bool {
+    let mut inside = false;
+    for (i, a) in polygon.iter().enumerate() {
+        let b = polygon[(i + 1) % polygon.len()];
+        if (a.y > pt.y) != (b.y > pt.y) && pt.x < a.x + (pt.y - a.y) / (b.y - a.y) * (b.x - a.x) {
+            inside = !inside;
+        }
+    }
+    inside
+}
+
+/// Whether the segments `[a0, a1]` and `[b0, b1]` have a common point.
+pub fn segments_intersect(
+    a0: Point2<f32>,
+    a1: Point2<f32>,
+    b0: Point2<f32>,
+    b1: Point2<f32>,
+) -> bool {
+    let cross = |o: Point2<f32>, p: Point2<f32>, q: Point2<f32>| {
+        (p.x - o.x) * (q.y - o.y) - (p.y - o.y) * (q.x - o.x)
+    };
+    let d0 = cross(b0, b1, a0);
+    let d1 = cross(b0, b1, a1);
+    let d2 = cross(a0, a1, b0);
+    let d3 = cross(a0, a1, b1);
+    if ((d0 > 0. && d1 < 0.) || (d0 < 0. && d1 > 0.))
+        && ((d2 > 0. && d3 < 0.) || (d2 < 0. && d3 > 0.))
+    {
+        return true;
+    }
+    // Touching or collinear.
+    let on = |p: Point2<f32>, q: Point2<f32>, r: Point2<f32>, d: f32| {
+        d == 0.
+            && r.x >= p.x.min(q.x)
+            && r.x <= p.x.max(q.x)
+            && r.y >= p.y.min(q.y)
+            && r.y <= p.y.max(q.y)
+    };
+    on(b0, b1, a0, d0) || on(b0, b1, a1, d1) || on(a0, a1, b0, d2) || on(a0, a1, b1, d3)
+}
+
+/// Closed shape in the xy plane, counter-clockwise.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Contour {
+    Circle { center: Point2<f32>, radius: f32 },
+    Quad([Point2<f32>; 4]),
+}
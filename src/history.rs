@@ -0,0 +1,180 @@
+use crate::{
+    document::{Document, StrokeId},
+    line::Line,
+};
+
+/// Changes kept for undo.
+pub const MAX_CHANGES: usize = 100;
+
+/// A line put in the document, or taken out of it, at `index` in the
+/// z-order.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Step {
+    Add {
+        index: usize,
+        id: StrokeId,
+        line: Line,
+    },
+    Remove {
+        index: usize,
+        id: StrokeId,
+        line: Line,
+    },
+}
+
+impl Step {
+    /// Take the line `id` out of the document, `None` when there is none.
+    pub fn remove(document: &mut Document, id: StrokeId) -> Option<Step> {
+        let index = document.index_of(id)?;
+        let line = document.remove(id)?;
+        Some(Step::Remove { index, id, line })
+    }
+
+    /// Put a new line at `index` of the document.
+    pub fn insert(document: &mut Document, index: usize, line: Line) -> Step {
+        let index = index.min(document.len());
+        let id = document.insert_at(index, line.clone());
+        Step::Add { index, id, line }
+    }
+
+    /// Put `line` in place of the line `id`, keeping its id and its index,
+    /// `None` when there is none.
+    pub fn replace(document: &mut Document, id: StrokeId, line: Line) -> Option<[Step; 2]> {
+        let index = document.index_of(id)?;
+        let old = document.remove(id)?;
+        document.insert_with_id(index, id, line.clone());
+        Some([
+            Step::Remove {
+                index,
+                id,
+                line: old,
+            },
+            Step::Add { index, id, line },
+        ])
+    }
+
+    pub fn id(&self) -> StrokeId {
+        match self {
+            Step::Add { id, .. } | Step::Remove { id, .. } => *id,
+        }
+    }
+
+    fn apply(&self, document: &mut Document) {
+        match self {
+            Step::Add { index, id, line } => document.insert_with_id(*index, *id, line.clone()),
+            Step::Remove { id, .. } => {
+                document.remove(*id);
+            }
+        }
+    }
+
+    fn revert(&self, document: &mut Document) {
+        match self {
+            Step::Add { id, .. } => {
+                document.remove(*id);
+            }
+            Step::Remove { index, id, line } => document.insert_with_id(*index, *id, line.clone()),
+        }
+    }
+}
+
+/// Steps undone and redone together, in the order they were made.
+pub type Change = std::vec::Vec<Step>;
+
+/// Changes made to a document, to undo and redo them.
+#[derive(Clone, Debug, Default)]
+pub struct History {
+    undo: std::vec::Vec<Change>,
+    redo: std::vec::Vec<Change>,
+}
+
+impl History {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Record a change already made to the document, which drops the undone
+    /// ones.
+    pub fn push(&mut self, change: Change) {
+        if change.is_empty() {
+            return;
+        }
+        self.redo.clear();
+        if self.undo.len() == MAX_CHANGES {
+            self.undo.remove(0);
+        }
+        self.undo.push(change);
+    }
+
+    /// Revert the last change, returning the ids of the lines it touched.
+    pub fn undo(&mut self, document: &mut Document) -> Option<std::vec::Vec<StrokeId>> {
+        let change = self.undo.pop()?;
+        for step in change.iter().rev() {
+            step.revert(document);
+        }
+        let ids = change.iter().map(Step::id).collect();
+        self.redo.push(change);
+        Some(ids)
+    }
+
+    /// Make the last undone change again, returning the ids of the lines it
+    /// touched.
+    pub fn redo(&mut self, document: &mut Document) -> Option<std::vec::Vec<StrokeId>> {
+        let change = self.redo.pop()?;
+        for step in &change {
+            step.apply(document);
+        }
+        let ids = change.iter().map(Step::id).collect();
+        self.undo.push(change);
+        Some(ids)
+    }
+
+    pub fn can_undo(&self) -> bool {
+        !self.undo.is_empty()
+    }
+
+    pub fn can_redo(&self) -> bool {
+        !self.redo.is_empty()
+    }
+
+    pub fn clear(&mut self) {
+        self.undo.clear();
+        self.redo.clear();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::line::test_line;
+
+    fn line(x: f32) -> Line {
+        test_line(&[(x, 0.)], 1.)
+    }
+
+    #[test]
+    fn test_undo_redo() {
+        let mut document = Document::new();
+        for x in [0., 1., 2.] {
+            document.push(line(x));
+        }
+        let before = document.clone();
+
+        let mut history = History::new();
+        let mut change = Change::new();
+        change.extend(Step::remove(&mut document, 2));
+        change.push(Step::insert(&mut document, 1, line(3.)));
+        change.extend(Step::remove(&mut document, 1));
+        history.push(change);
+        assert_eq!(document.ids(), &[4, 3]);
+        let after = document.clone();
+
+        assert_eq!(history.undo(&mut document), Some(std::vec![2, 4, 1]));
+        // Ids are not reused, so only the lines are the same as before.
+        assert!(document.iter().eq(before.iter()));
+        assert!(history.undo(&mut document).is_none());
+        assert!(history.redo(&mut document).is_some());
+        assert_eq!(document, after);
+        assert!(!history.can_redo());
+    }
+}
@@ -0,0 +1,125 @@
+use std::collections::BTreeMap;
+
+use rstar::{
+    primitives::{GeomWithData, Rectangle},
+    RTree, AABB,
+};
+
+use crate::geometry::Rect;
+
+type Entry = GeomWithData<Rectangle<[f64; 2]>, u64>;
+
+/// Areas by id, to find the ones meeting a rectangle without going through
+/// all of them.
+#[derive(Clone, Debug, Default)]
+pub struct SpatialIndex {
+    tree: RTree<Entry>,
+    rects: BTreeMap<u64, Rect<f64>>,
+}
+
+impl SpatialIndex {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Set the area of `id`, replacing any previous one.
+    pub fn insert(&mut self, id: u64, rect: Rect<f64>) {
+        self.remove(id);
+        self.tree.insert(entry(id, &rect));
+        self.rects.insert(id, rect);
+    }
+
+    pub fn remove(&mut self, id: u64) -> Option<Rect<f64>> {
+        let rect = self.rects.remove(&id)?;
+        self.tree.remove(&entry(id, &rect));
+        Some(rect)
+    }
+
+    pub fn get(&self, id: u64) -> Option<&Rect<f64>> {
+        self.rects.get(&id)
+    }
+
+    /// Ids whose area meets `rect`, in no particular order.
+    pub fn query(&self, rect: &Rect<f64>) -> impl Iterator<Item = u64> + '_ {
+        self.tree
+            .locate_in_envelope_intersecting(&envelope(rect))
+            .map(|entry| entry.data)
+    }
+
+    /// Area covering all others, kept up to date by the tree.
+    pub fn bounds(&self) -> Option<Rect<f64>> {
+        if self.is_empty() {
+            return None;
+        }
+        let envelope = self.tree.root().envelope();
+        let (min, max) = (envelope.lower(), envelope.upper());
+        Some(Rect::new(min.into(), max.into()))
+    }
+
+    pub fn len(&self) -> usize {
+        self.rects.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.rects.is_empty()
+    }
+
+    pub fn clear(&mut self) {
+        self.tree = RTree::new();
+        self.rects.clear();
+    }
+}
+
+impl PartialEq for SpatialIndex {
+    fn eq(&self, other: &Self) -> bool {
+        self.rects == other.rects
+    }
+}
+
+fn envelope(rect: &Rect<f64>) -> AABB<[f64; 2]> {
+    AABB::from_corners([rect.min.x, rect.min.y], [rect.max.x, rect.max.y])
+}
+
+fn entry(id: u64, rect: &Rect<f64>) -> Entry {
+    GeomWithData::new(
+        Rectangle::from_corners([rect.min.x, rect.min.y], [rect.max.x, rect.max.y]),
+        id,
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use cgmath::Point2;
+
+    use super::*;
+
+    #[test]
+    fn test_query() {
+        let mut index = SpatialIndex::new();
+        index.insert(1, Rect::new(Point2::new(0., 0.), Point2::new(1., 1.)));
+        index.insert(2, Rect::new(Point2::new(5., 5.), Point2::new(6., 8.)));
+        index.insert(3, Rect::around(Point2::new(-10., 3.), 0.5));
+        let query = |index: &SpatialIndex, rect: Rect<f64>| {
+            let mut ids: std::vec::Vec<_> = index.query(&rect).collect();
+            ids.sort();
+            ids
+        };
+        let rect = Rect::new(Point2::new(0.5, 0.5), Point2::new(5., 5.));
+        assert_eq!(query(&index, rect), [1, 2]);
+
+        index.insert(2, Rect::around(Point2::new(-10., 3.), 1.));
+        assert_eq!(query(&index, rect), [1]);
+        assert_eq!(query(&index, Rect::around(Point2::new(-11., 3.), 0.)), [2]);
+
+        assert!(index.remove(1).is_some());
+        assert!(index.remove(1).is_none());
+        assert_eq!(index.len(), 2);
+        assert!(query(&index, rect).is_empty());
+        assert_eq!(
+            index.bounds(),
+            Some(Rect::new(Point2::new(-11., 2.), Point2::new(-9., 4.)))
+        );
+        index.clear();
+        assert_eq!(index.bounds(), None);
+    }
+}
@@ -0,0 +1,354 @@
+use std::{collections::BTreeMap, fmt::Write, io};
+
+use crate::{
+    document::Document,
+    error::invalid_data,
+    line::{Line, Sample},
+    point::{self, Point},
+};
+
+/// Channels the traces may have, with the attributes of their declaration.
+/// `W` is the stroke width, `R`, `G`, `B` and `A` the linear color of the
+/// points.
+const CHANNELS: &[(&str, &str)] = &[
+    ("X", r#"type="decimal""#),
+    ("Y", r#"type="decimal""#),
+    ("W", r#"type="decimal""#),
+    ("F", r#"type="decimal" min="0" max="1""#),
+    ("T", r#"type="decimal" units="ms""#),
+    ("R", r#"type="decimal" min="0" max="1""#),
+    ("G", r#"type="decimal" min="0" max="1""#),
+    ("B", r#"type="decimal" min="0" max="1""#),
+    ("A", r#"type="decimal" min="0" max="1""#),
+];
+
+/// Write every line as an InkML trace, with the X, Y and W channels. Lines
+/// with samples get the F and T channels too, and lines of several colors
+/// the R, G, B and A channels.
+///
+/// Y points down as in most ink formats. The brush holds the color and the
+/// width of the first point, for readers ignoring the channels: a brush width
+/// is the diameter at zero force, a point being `width * (1 + 2 * F) / 2`
+/// wide like with [`point::Pen`].
+pub fn export_inkml(document: &Document) -> String {
+    let mut brush_v: BTreeMap<String, usize> = BTreeMap::new();
+    let mut context_v: BTreeMap<String, String> = BTreeMap::new();
+    let mut traces = String::new();
+    for (_, line) in document.iter() {
+        let points = line.world_points();
+        let (first, sample) = match points.first() {
+            Some(first) => (first, line.samples().first()),
+            None => continue,
+        };
+        let base = first.width / (1. + 2. * sample.map_or(0., |sample| sample.force));
+        let brush = format!(
+            r#"<brushProperty name="width" value="{}"/><brushProperty name="color" value="{}"/><brushProperty name="transparency" value="{}"/>"#,
+            base * 2.,
+            point::srgb_hex(&first.color),
+            ((1. - first.color[3].clamp(0., 1.)) * 255.).round() as u8
+        );
+        let count = brush_v.len();
+        let brush_i = *brush_v.entry(brush).or_insert(count);
+
+        let sampled = !line.samples().is_empty();
+        let colored = points.iter().any(|pt| pt.color != first.color);
+        let channels: Vec<_> = CHANNELS
+            .iter()
+            .filter(|(name, _)| match *name {
+                "F" | "T" => sampled,
+                "R" | "G" | "B" | "A" => colored,
+                _ => true,
+            })
+            .collect();
+        let context: String = channels.iter().map(|(name, _)| *name).collect();
+        context_v.entry(context.clone()).or_insert_with(|| {
+            channels
+                .iter()
+                .map(|(name, attributes)| format!(r#"<channel name="{}" {}/>"#, name, attributes))
+                .collect()
+        });
+        let _ = write!(
+            traces,
+            r##"<trace contextRef="#ctx{}" brushRef="#br{}">"##,
+            context, brush_i
+        );
+        for (i, pt) in points.iter().enumerate() {
+            if i > 0 {
+                traces.push_str(", ");
+            }
+            let _ = write!(traces, "{} {} {}", pt.pos.x, 0. - pt.pos.y, pt.width * 2.);
+            if let Some(sample) = line.samples().get(i) {
+                let _ = write!(traces, " {} {}", sample.force, sample.time);
+            }
+            if colored {
+                for c in pt.color {
+                    let _ = write!(traces, " {}", c);
+                }
+            }
+        }
+        traces.push_str("</trace>\n");
+    }
+
+    let mut brushes: Vec<_> = brush_v.into_iter().collect();
+    brushes.sort_by_key(|(_, i)| *i);
+    let mut inkml = String::from("<ink xmlns=\"http://www.w3.org/2003/InkML\">\n<definitions>\n");
+    for (context, channels) in context_v {
+        let _ = writeln!(
+            inkml,
+            r#"<context xml:id="ctx{}"><traceFormat>{}</traceFormat></context>"#,
+            context, channels
+        );
+    }
+    for (brush, i) in brushes {
+        let _ = writeln!(inkml, r#"<brush xml:id="br{}">{}</brush>"#, i, brush);
+    }
+    inkml.push_str("</definitions>\n");
+    inkml.push_str(&traces);
+    inkml.push_str("</ink>\n");
+    inkml
+}
+
+/// Read the traces of an InkML document as lines, keeping force and time
+/// as samples when present. Widths and colors come from the channels when
+/// present, from the brush otherwise. Inverse of [`export_inkml`].
+pub fn import_inkml(text: &str) -> io::Result<Vec<Line>> {
+    let tree = roxmltree::Document::parse(text).map_err(invalid_data)?;
+    let by_id = |id: &str| {
+        let id = id.trim_start_matches('#');
+        tree.descendants()
+            .find(|node| node.attribute(("http://www.w3.org/XML/1998/namespace", "id")) == Some(id))
+    };
+
+    let mut lines = Vec::new();
+    for trace in tree
+        .descendants()
+        .filter(|node| node.tag_name().name() == "trace")
+    {
+        // Closest reference wins: trace, then enclosing trace groups.
+        let reference = |name: &str| {
+            trace
+                .ancestors()
+                .find_map(|node| node.attribute(name))
+                .and_then(by_id)
+        };
+        let context = reference("contextRef").or_else(|| {
+            tree.descendants()
+                .find(|node| node.tag_name().name() == "context")
+        });
+        let format = context
+            .and_then(|context| {
+                context
+                    .children()
+                    .find(|node| node.tag_name().name() == "traceFormat")
+                    .or_else(|| context.attribute("traceFormatRef").and_then(by_id))
+            })
+            .or_else(|| {
+                tree.descendants()
+                    .find(|node| node.tag_name().name() == "traceFormat")
+            });
+        let channels: Vec<(String, Option<f64>)> = match format {
+            Some(format) => format
+                .descendants()
+                .filter(|node| node.tag_name().name() == "channel")
+                .map(|node| {
+                    (
+                        node.attribute("name").unwrap_or_default().to_string(),
+                        node.attribute("max").and_then(|max| max.parse().ok()),
+                    )
+                })
+                .collect(),
+            None => vec![("X".to_string(), None), ("Y".to_string(), None)],
+        };
+        let brush = reference("brushRef").or_else(|| {
+            context
+                .and_then(|context| context.attribute("brushRef"))
+                .and_then(by_id)
+        });
+        let brush_property = |name: &str| {
+            brush.and_then(|brush| {
+                brush
+                    .children()
+                    .find(|node| node.attribute("name") == Some(name))
+                    .and_then(|node| node.attribute("value"))
+            })
+        };
+        let base = brush_property("width")
+            .and_then(|value| value.parse::<f32>().ok())
+            .unwrap_or(1.)
+            / 2.;
+        let mut color = brush_property("color")
+            .and_then(|value| value.parse::<svgtypes::Color>().ok())
+            .map_or([0., 0., 0., 1.], |color| {
+                [
+                    point::srgb_to_linear(color.red as f32 / 255.),
+                    point::srgb_to_linear(color.green as f32 / 255.),
+                    point::srgb_to_linear(color.blue as f32 / 255.),
+                    1.,
+                ]
+            });
+        if let Some(transparency) =
+            brush_property("transparency").and_then(|value| value.parse::<f32>().ok())
+        {
+            color[3] = 1. - transparency.clamp(0., 255.) / 255.;
+        }
+
+        let values = decode_trace(trace.text().unwrap_or_default(), channels.len())?;
+        let channel = |name: &str| channels.iter().position(|(o_name, _)| o_name == name);
+        let (x_i, y_i) = match (channel("X"), channel("Y")) {
+            (Some(x_i), Some(y_i)) => (x_i, y_i),
+            _ => return Err(invalid_data("trace without X and Y")),
+        };
+        let (f_i, t_i, w_i) = (channel("F"), channel("T"), channel("W"));
+        let color_i = match (channel("R"), channel("G"), channel("B")) {
+            (Some(r_i), Some(g_i), Some(b_i)) => Some([r_i, g_i, b_i]),
+            _ => None,
+        };
+        let a_i = channel("A");
+        // Without a declared maximum, assume the largest force is full pressure.
+        let f_max = f_i
+            .map(|f_i| {
+                channels[f_i]
+                    .1
+                    .unwrap_or_else(|| values.iter().map(|value| value[f_i]).fold(1., f64::max))
+            })
+            .unwrap_or(1.);
+
+        let mut points = Vec::new();
+        let mut samples = Vec::new();
+        for value in &values {
+            let force = f_i.map_or(0., |f_i| (value[f_i] / f_max) as f32);
+            let mut color = color;
+            if let Some(color_i) = color_i {
+                for (c, i) in color.iter_mut().zip(color_i) {
+                    *c = value[i] as f32;
+                }
+            }
+            if let Some(a_i) = a_i {
+                color[3] = value[a_i] as f32;
+            }
+            points.push(Point {
+                pos: [value[x_i] as f32, 0. - value[y_i] as f32, 0.].into(),
+                color,
+                width: w_i.map_or(base * (1. + 2. * force), |w_i| value[w_i] as f32 / 2.),
+            });
+            if f_i.is_some() || t_i.is_some() {
+                samples.push(Sample {
+                    time: t_i.map_or(0., |t_i| value[t_i]),
+                    force,
+                });
+            }
+        }
+        if !points.is_empty() {
+            lines.push(Line::from_samples(points, samples));
+        }
+    }
+    Ok(lines)
+}
+
+/// Decode the points of a trace, resolving the `'` (first difference) and
+/// `"` (second difference) qualifiers, which stick to their channel.
+fn decode_trace(text: &str, channel_count: usize) -> io::Result<Vec<Vec<f64>>> {
+    let mut values = Vec::new();
+    let mut value = vec![0.; channel_count];
+    let mut velocity = vec![0.; channel_count];
+    let mut order = vec![0; channel_count];
+    for pt in text.split(',').filter(|pt| !pt.trim().is_empty()) {
+        for (c, token) in pt.split_whitespace().take(channel_count).enumerate() {
+            let number = match token.as_bytes()[0] {
+                b'!' => {
+                    order[c] = 0;
+                    &token[1..]
+                }
+                b'\'' => {
+                    order[c] = 1;
+                    &token[1..]
+                }
+                b'"' => {
+                    order[c] = 2;
+                    &token[1..]
+                }
+                _ => token,
+            };
+            let number: f64 = number
+                .parse()
+                .map_err(|_| invalid_data(format!("bad trace value {:?}", token)))?;
+            let last = value[c];
+            match order[c] {
+                0 => value[c] = number,
+                1 => value[c] += number,
+                _ => value[c] += velocity[c] + number,
+            }
+            velocity[c] = value[c] - last;
+        }
+        values.push(value.clone());
+    }
+    Ok(values)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_round_trip() {
+        let pt = |x: f32, width: f32| Point {
+            pos: [x, 1., 0.].into(),
+            color: [0., 0., 0., 1.],
+            width,
+        };
+        let mut document = Document::new();
+        document.push(Line::from_samples(
+            vec![pt(0., 0.25), pt(1., 0.375)],
+            vec![
+                Sample {
+                    time: 0.,
+                    force: 0.5,
+                },
+                Sample {
+                    time: 8.,
+                    force: 1.,
+                },
+            ],
+        ));
+        document.push(Line::from_points(vec![pt(0., 0.5)]));
+        let lines = import_inkml(&export_inkml(&document)).unwrap();
+        let o_lines: Vec<_> = document.iter().map(|(_, line)| line.clone()).collect();
+        assert_eq!(lines, o_lines);
+    }
+
+    #[test]
+    fn test_channels() {
+        let mut document = Document::new();
+        document.push(Line::from_points(vec![
+            Point {
+                pos: [0., 1., 0.].into(),
+                color: [1., 0., 0., 1.],
+                width: 0.25,
+            },
+            Point {
+                pos: [1., 1., 0.].into(),
+                color: [0., 0.5, 1., 0.5],
+                width: 0.75,
+            },
+        ]));
+        let inkml = export_inkml(&document);
+        assert!(inkml.contains(r##"contextRef="#ctxXYWRGBA""##));
+        let lines = import_inkml(&inkml).unwrap();
+        let (_, line) = document.iter().next().unwrap();
+        assert_eq!(lines[0].points(), line.points());
+    }
+
+    #[test]
+    fn test_differences() {
+        let text = r#"<ink xmlns="http://www.w3.org/2003/InkML">
+            <trace>10 0, '1 '2, "1 "0, 3 4</trace>
+        </ink>"#;
+        let lines = import_inkml(text).unwrap();
+        let xy: Vec<_> = lines[0]
+            .points()
+            .iter()
+            .map(|pt| (pt.pos.x, pt.pos.y))
+            .collect();
+        assert_eq!(xy, [(10., 0.), (11., -2.), (13., -4.), (18., -10.)]);
+    }
+}
@@ -1,14 +1,32 @@
-mod line;
+use std::{
+    collections::HashSet,
+    io::{self, Error},
+    time::Duration,
+};
 
-use std::io::{self, Error};
-
-use cgmath::Vector3;
+use cgmath::{EuclideanSpace, InnerSpace, SquareMatrix, Vector3};
 use wgpu::{util::DeviceExt, Instance, Surface};
 use winit::dpi::PhysicalSize;
 
+mod error;
+
 // Public
+pub mod animation;
 pub mod camera;
+pub mod document;
+pub mod eraser;
+pub mod file;
+pub mod geometry;
+pub mod history;
+pub mod index;
+pub mod inkml;
+pub mod line;
+pub mod pdf;
+pub mod plotter;
 pub mod point;
+pub mod selection;
+pub mod svg;
+pub mod xopp;
 
 pub trait AsCanvas {
     fn get_size(&self) -> &PhysicalSize<u32>;
@@ -17,12 +35,41 @@ pub trait AsCanvas {
 
     fn render(&mut self) -> Result<(), wgpu::SurfaceError>;
 
+    /// Advance the camera animations by `dt`.
+    fn update(&mut self, dt: Duration);
+
+    /// Whether something changed since the last `render`, or an animation is
+    /// running.
+    fn needs_redraw(&self) -> bool;
+
     /// Point in view
     fn push_point(&mut self, pt: point::Point);
 
     /// Point in view
     fn start_line(&mut self, pt: point::Point);
 
+    /// Like `push_point`, recording the pen state too.
+    fn push_sample(&mut self, pt: point::Point, sample: line::Sample);
+
+    /// Like `start_line`, recording the pen state too.
+    fn start_sampled_line(&mut self, pt: point::Point, sample: line::Sample);
+
+    /// Start a line of `pen` under the pixel `screen_px`.
+    fn start_pen_line(
+        &mut self,
+        pen: &point::Pen,
+        screen_px: cgmath::Point2<f32>,
+        sample: line::Sample,
+    );
+
+    /// Continue the line of `pen` under the pixel `screen_px`.
+    fn push_pen_point(
+        &mut self,
+        pen: &point::Pen,
+        screen_px: cgmath::Point2<f32>,
+        sample: line::Sample,
+    );
+
     fn end_line(&mut self);
 
     fn cancle_line(&mut self);
@@ -34,6 +81,173 @@ pub trait AsCanvas {
     fn move_content(&mut self, x: f32, y: f32, z: f32);
 
     fn scacle(&mut self, x: f32, y: f32, z: f32);
+
+    /// Rotate the content around the view z axis through `pivot`, a point in
+    /// view.
+    fn rotate_content(&mut self, angle: cgmath::Rad<f32>, pivot: cgmath::Point3<f32>);
+
+    /// Undo any rotation, keeping `pivot` in place.
+    fn reset_rotation(&mut self, pivot: cgmath::Point3<f32>);
+
+    /// Show all strokes, with `padding` world units around them.
+    fn fit_to_content(&mut self, padding: f32);
+
+    /// Show the world area `rect`, as large as the viewport allows.
+    fn fit_to_rect(&mut self, rect: &geometry::Rect<f64>);
+
+    /// Like `zoom_at`, eased over `duration`.
+    fn animate_zoom_at(&mut self, screen_px: cgmath::Point2<f32>, factor: f32, duration: Duration);
+
+    /// Move the content by `delta` pixels, eased over `duration`.
+    fn animate_pan(&mut self, delta: cgmath::Vector2<f32>, duration: Duration);
+
+    /// Keep panning at `velocity` pixels per second, slowing down until it
+    /// stops. Meant for the end of a pan gesture.
+    fn fling(&mut self, velocity: cgmath::Vector2<f32>);
+
+    /// Stop any camera animation where it is. Other camera changes stop them
+    /// too.
+    fn stop_motion(&mut self);
+
+    /// Save the current view in the document under `name`, replacing any
+    /// bookmark of that name.
+    fn add_bookmark(&mut self, name: &str);
+
+    /// Returns whether there was such a bookmark.
+    fn remove_bookmark(&mut self, name: &str) -> bool;
+
+    /// Go to the view saved under `name`, eased over `duration` or at once
+    /// when it is zero. Returns whether there was such a bookmark.
+    fn go_to_bookmark(&mut self, name: &str, duration: Duration) -> bool;
+
+    fn get_limits(&self) -> &camera::Limits;
+
+    /// Constrain the zoom and the pan of every later camera change, and of
+    /// the current view. The limits are kept normalized, see
+    /// [`camera::Limits::normalized`].
+    fn set_limits(&mut self, limits: camera::Limits);
+
+    fn get_projection(&self) -> camera::Projection;
+
+    /// Point of the world plane `z = 0` under the pixel `px`, `None` when the
+    /// view is degenerate.
+    fn screen_to_world(&self, px: cgmath::Point2<f32>) -> Option<cgmath::Point3<f64>>;
+
+    /// Pixel where the world point is drawn.
+    fn world_to_screen(&self, pt: cgmath::Point3<f64>) -> cgmath::Point2<f32>;
+
+    fn set_projection(&mut self, projection: camera::Projection);
+
+    fn get_width_mode(&self) -> line::WidthMode;
+
+    /// Unit of the widths of the lines started later.
+    fn set_width_mode(&mut self, width_mode: line::WidthMode);
+
+    /// Strokes within `tolerance` pixels of the pixel `screen_px`, from top to
+    /// bottom.
+    fn hit_test(&self, screen_px: cgmath::Point2<f32>, tolerance: f32) -> Vec<document::StrokeId>;
+
+    fn get_eraser_mode(&self) -> eraser::EraserMode;
+
+    /// What the erasers started later remove.
+    fn set_eraser_mode(&mut self, mode: eraser::EraserMode);
+
+    /// Start dragging an eraser of `radius` pixels at the pixel `screen_px`,
+    /// removing the strokes, or the parts of them, under it.
+    fn start_erase(&mut self, screen_px: cgmath::Point2<f32>, radius: f32);
+
+    /// Drag the eraser to the pixel `screen_px`, removing the strokes, or the
+    /// parts of them, it sweeps over.
+    fn erase_to(&mut self, screen_px: cgmath::Point2<f32>);
+
+    /// Stop erasing, the changes since `start_erase` making one undo step.
+    fn end_erase(&mut self);
+
+    /// Select the strokes with a point inside the closed polygon of pixels
+    /// `polygon`, or crossing its border.
+    fn select_lasso(&mut self, polygon: &[cgmath::Point2<f32>]) -> &selection::Selection;
+
+    /// Like `select_lasso`, for the rectangle of pixels between two corners.
+    fn select_rect(
+        &mut self,
+        corner0: cgmath::Point2<f32>,
+        corner1: cgmath::Point2<f32>,
+    ) -> &selection::Selection;
+
+    fn get_selection(&self) -> &selection::Selection;
+
+    /// Forget the selection, committing any transform of it.
+    fn clear_selection(&mut self);
+
+    /// Pixels where the handles of the selection box are.
+    fn selection_handles(&self) -> Vec<(selection::Handle, cgmath::Point2<f32>)>;
+
+    /// Move the selected strokes by `delta` pixels, until
+    /// `commit_transform`.
+    fn move_selection(&mut self, delta: cgmath::Vector2<f32>);
+
+    /// Scale the selected strokes by `factor`, keeping what is under the
+    /// pixel `pivot` in place, until `commit_transform`.
+    fn scale_selection(&mut self, factor: f32, pivot: cgmath::Point2<f32>);
+
+    /// Rotate the selected strokes counter-clockwise in the world, keeping
+    /// what is under the pixel `pivot` in place, until `commit_transform`.
+    fn rotate_selection(&mut self, angle: cgmath::Rad<f32>, pivot: cgmath::Point2<f32>);
+
+    /// Bake the transform of the selected strokes into their points, as one
+    /// undo step.
+    fn commit_transform(&mut self);
+
+    /// Put the selected strokes back where they were before the transform.
+    fn cancel_transform(&mut self);
+
+    /// Revert the last change of the strokes. Returns whether there was one.
+    fn undo(&mut self) -> bool;
+
+    /// Make the last undone change again. Returns whether there was one.
+    fn redo(&mut self) -> bool;
+
+    /// Scale the content by `factor`, keeping what is under the pixel
+    /// `screen_px` in place.
+    fn zoom_at(&mut self, screen_px: cgmath::Point2<f32>, factor: f32);
+
+    /// Rotate the content, keeping what is under the pixel `screen_px` in
+    /// place.
+    fn rotate_at(&mut self, screen_px: cgmath::Point2<f32>, angle: cgmath::Rad<f32>);
+
+    /// Encode the drawing and the view in the native file format.
+    fn save(&self, format: file::Format) -> io::Result<Vec<u8>>;
+
+    /// Replace the drawing and the view with the content of a native file.
+    fn load(&mut self, data: &[u8]) -> io::Result<()>;
+
+    fn export_svg(&self, view_box: svg::ViewBox) -> String;
+
+    /// Add the shapes of an SVG image as lines, flattened for the current
+    /// zoom. Returns the new ids.
+    fn import_svg(&mut self, text: &str) -> io::Result<Vec<u64>>;
+
+    fn export_inkml(&self) -> String;
+
+    /// Add the traces of an InkML document as lines. Returns the new ids.
+    fn import_inkml(&mut self, text: &str) -> io::Result<Vec<u64>>;
+
+    /// Add the pages and strokes of a Xournal++ file below the existing
+    /// pages. Returns the new line ids.
+    fn import_xopp(&mut self, data: &[u8]) -> io::Result<Vec<u64>>;
+
+    fn export_xopp(&self) -> io::Result<Vec<u8>>;
+
+    fn export_plot(
+        &self,
+        config: &plotter::PlotterConfig,
+        dialect: plotter::Dialect,
+    ) -> io::Result<plotter::Plot>;
+
+    /// Export one PDF page per world area in `regions`, at `scale` points per
+    /// world unit. Without regions, the document pages are exported, or the
+    /// content when there are none.
+    fn export_pdf(&self, regions: &[geometry::Rect<f64>], scale: f32) -> Vec<u8>;
 }
 
 pub struct Canvas {
@@ -47,13 +261,30 @@ pub struct Canvas {
     render_pipeline: wgpu::RenderPipeline,
 
     s_line: Option<line::Line>,
-    next_id: u64,
+    s_buffer: Option<line::LineBuffer>,
+    document: document::Document,
     lines: std::collections::BTreeMap<u64, line::LineBuffer>,
 
     camera: camera::Camera,
     camera_uniform: camera::CameraUniform,
     camera_buffer: wgpu::Buffer,
     camera_bind_group: wgpu::BindGroup,
+    limits: camera::Limits,
+    /// Camera origin the offsets of the line buffers are relative to.
+    buffer_origin: cgmath::Vector2<f64>,
+    width_mode: line::WidthMode,
+    history: history::History,
+    eraser: Option<eraser::Eraser>,
+    eraser_mode: eraser::EraserMode,
+    selection: selection::Selection,
+    /// World transform of the selected lines, drawn but not yet baked into
+    /// them.
+    selection_transform: cgmath::Matrix3<f64>,
+    /// Lines drawn over the document, like the selection box.
+    overlay: Vec<(line::Line, line::LineBuffer)>,
+    motion: Option<animation::Motion>,
+    /// Whether something changed since the last render.
+    redraw: bool,
 }
 
 impl Canvas {
@@ -69,7 +300,7 @@ impl Canvas {
                 force_fallback_adapter: false,
             })
             .await
-            .ok_or(Error::new(io::ErrorKind::Other, "no adapter"))?;
+            .ok_or(Error::other("no adapter"))?;
         log::info!("found adapter: {:?}", adapter.get_info());
 
         let (device, queue) = adapter
@@ -88,7 +319,7 @@ impl Canvas {
                 None, // Trace path
             )
             .await
-            .map_err(|e| Error::new(io::ErrorKind::Other, e))?;
+            .map_err(Error::other)?;
         log::info!("found device: {:?}", device);
 
         let surface_caps = surface.get_capabilities(&adapter);
@@ -99,8 +330,7 @@ impl Canvas {
             .formats
             .iter()
             .copied()
-            .filter(|f| f.describe().srgb)
-            .next()
+            .find(|f| f.describe().srgb)
             .unwrap_or(surface_caps.formats[0]);
         let config = wgpu::SurfaceConfiguration {
             usage: wgpu::TextureUsages::RENDER_ATTACHMENT,
@@ -176,8 +406,8 @@ impl Canvas {
             layout: Some(&render_pipeline_layout),
             vertex: wgpu::VertexState {
                 module: &shader,
-                entry_point: "vs_main",           // 1.
-                buffers: &[line::Vertex::desc()], // 2.
+                entry_point: "vs_main",                                   // 1.
+                buffers: &[line::Vertex::desc(), line::Instance::desc()], // 2.
             },
             fragment: Some(wgpu::FragmentState {
                 // 3.
@@ -218,15 +448,213 @@ impl Canvas {
             config,
             size,
             render_pipeline,
-            next_id: 1,
             s_line: None,
+            s_buffer: None,
+            document: document::Document::new(),
             lines: std::collections::BTreeMap::new(),
             camera,
             camera_uniform,
             camera_buffer,
             camera_bind_group,
+            limits: camera::Limits::default(),
+            buffer_origin: cgmath::Vector2::new(0., 0.),
+            width_mode: line::WidthMode::World,
+            history: history::History::new(),
+            eraser: None,
+            eraser_mode: eraser::EraserMode::Stroke,
+            selection: selection::Selection::new(),
+            selection_transform: cgmath::Matrix3::identity(),
+            overlay: Vec::new(),
+            motion: None,
+            redraw: true,
         })
     }
+
+    pub fn get_document(&self) -> &document::Document {
+        &self.document
+    }
+
+    /// Apply the limits and upload the camera, which ends any running
+    /// animation.
+    fn update_camera(&mut self) {
+        self.motion = None;
+        self.redraw = true;
+        let document = &self.document;
+        self.camera.constrain(&self.limits, || document.bounds());
+        self.camera.rebase();
+        self.camera_uniform.update(&self.camera);
+        self.queue.write_buffer(
+            &self.camera_buffer,
+            0,
+            bytemuck::cast_slice(&[self.camera_uniform]),
+        );
+        if self.camera.origin() != self.buffer_origin {
+            for (id, buffer) in &self.lines {
+                if let Some(line) = self.document.get(*id) {
+                    buffer.update(line, self);
+                }
+            }
+            if let (Some(line), Some(buffer)) = (&self.s_line, &self.s_buffer) {
+                buffer.update(line, self);
+            }
+            for (line, buffer) in &self.overlay {
+                buffer.update(line, self);
+            }
+            self.buffer_origin = self.camera.origin();
+        }
+    }
+
+    /// Position in the line being drawn of a point in view.
+    fn to_line(&self, pos: &cgmath::Point3<f32>) -> cgmath::Point3<f32> {
+        let pos = self.camera.untransform_point(pos);
+        match &self.s_line {
+            Some(line) => {
+                let shift = self.camera.origin() - line.origin();
+                cgmath::Point3::new(
+                    (pos.x as f64 + shift.x) as f32,
+                    (pos.y as f64 + shift.y) as f32,
+                    pos.z,
+                )
+            }
+            None => pos,
+        }
+    }
+
+    /// World point of the plane `z = 0` under the pixel `screen_px`, `None`
+    /// when the view is degenerate.
+    fn to_world(&self, screen_px: cgmath::Point2<f32>) -> Option<cgmath::Point2<f64>> {
+        let pt = self.camera.screen_to_world(screen_px, self.size)?;
+        Some(cgmath::Point2::new(pt.x, pt.y))
+    }
+
+    /// Follow the document for the lines `ids`, which were added, removed or
+    /// changed.
+    fn sync_buffers(&mut self, ids: &[u64]) {
+        self.redraw = true;
+        for id in ids {
+            match self.document.get(*id) {
+                Some(line) => {
+                    let buffer = line::LineBuffer::new(line, self);
+                    self.lines.insert(*id, buffer);
+                }
+                None => {
+                    self.lines.remove(id);
+                }
+            }
+        }
+        // Undoing a transform changes selected lines without removing them.
+        let changed = ids.iter().any(|id| self.selection.contains(*id));
+        if self.selection.retain(&self.document) || changed {
+            self.update_overlay();
+        }
+    }
+
+    /// Compose `transform` with the world transform of the selected lines.
+    fn transform_selection(&mut self, transform: cgmath::Matrix3<f64>) {
+        self.set_selection_transform(transform * self.selection_transform);
+    }
+
+    fn set_selection_transform(&mut self, transform: cgmath::Matrix3<f64>) {
+        self.selection_transform = transform;
+        for id in self.selection.ids() {
+            if let Some(buffer) = self.lines.get_mut(id) {
+                buffer.set_transform(self.selection_transform);
+            }
+        }
+        for id in self.selection.ids() {
+            if let (Some(line), Some(buffer)) = (self.document.get(*id), self.lines.get(id)) {
+                buffer.update(line, self);
+            }
+        }
+        self.update_overlay();
+    }
+
+    /// Forget the selection and its transform.
+    fn reset_selection(&mut self) {
+        self.selection = selection::Selection::new();
+        self.selection_transform = cgmath::Matrix3::identity();
+        self.update_overlay();
+    }
+
+    /// Rebuild the lines drawn over the document.
+    fn update_overlay(&mut self) {
+        self.redraw = true;
+        let lines: Vec<_> = self
+            .selection
+            .bounds(&self.document)
+            .map(|rect| selection::overlay(&rect, &self.selection_transform))
+            .unwrap_or_default();
+        self.overlay = lines
+            .into_iter()
+            .map(|line| {
+                let buffer = line::LineBuffer::new(&line, self);
+                (line, buffer)
+            })
+            .collect();
+    }
+
+    /// Rebuild the buffer of the line being drawn, so that it shows from its
+    /// first point.
+    fn update_s_buffer(&mut self) {
+        self.redraw = true;
+        self.s_buffer = self
+            .s_line
+            .as_ref()
+            .map(|line| line::LineBuffer::new(line, self));
+    }
+
+    /// World size of a pixel on the plane `z = 0`.
+    fn px_size(&self) -> f32 {
+        self.camera
+            .visible_height()
+            .map_or(1., |height| height / self.size.height.max(1) as f32)
+    }
+
+    /// Go to the view matrix `vm`, relative to the camera origin `origin`.
+    fn animate_to(
+        &mut self,
+        origin: cgmath::Vector2<f64>,
+        vm: cgmath::Matrix4<f32>,
+        duration: Duration,
+    ) {
+        self.camera.set_origin(origin);
+        if duration.is_zero() {
+            self.camera.set_vm(vm);
+            self.update_camera();
+        } else {
+            self.motion = Some(animation::Motion::Transition {
+                origin,
+                from: *self.camera.vm(),
+                to: vm,
+                duration,
+                elapsed: Duration::ZERO,
+            });
+            self.redraw = true;
+        }
+    }
+
+    /// Put lines on top of the others, returning their ids.
+    fn add_lines(&mut self, lines: Vec<line::Line>) -> Vec<u64> {
+        self.redraw = true;
+        lines
+            .into_iter()
+            .map(|line| {
+                let buffer = line::LineBuffer::new(&line, self);
+                let id = self.document.push(line);
+                self.lines.insert(id, buffer);
+                id
+            })
+            .collect()
+    }
+
+    fn rebuild_buffers(&mut self) {
+        self.redraw = true;
+        self.lines = self
+            .document
+            .iter()
+            .map(|(id, line)| (id, line::LineBuffer::new(line, self)))
+            .collect();
+    }
 }
 
 impl AsCanvas for Canvas {
@@ -235,7 +663,7 @@ impl AsCanvas for Canvas {
     }
 
     fn resize(&mut self, new_size: winit::dpi::PhysicalSize<u32>) {
-        if new_size.width <= 0 || new_size.height <= 0 {
+        if new_size.width == 0 || new_size.height == 0 {
             return;
         }
 
@@ -253,6 +681,35 @@ impl AsCanvas for Canvas {
             .texture
             .create_view(&wgpu::TextureViewDescriptor::default());
 
+        // Only the lines drawn are tessellated for the current zoom.
+        let px_size = self.px_size();
+        let visible: Option<HashSet<u64>> = self
+            .camera
+            .visible_rect(0.)
+            .map(|rect| self.document.query(&rect, px_size).collect());
+        let drawn: Vec<u64> = self
+            .document
+            .ids()
+            .iter()
+            .copied()
+            .filter(|id| {
+                // The index does not know where the selection is moved to.
+                visible.as_ref().is_none_or(|visible| visible.contains(id))
+                    || self.selection.contains(*id)
+            })
+            .collect();
+        for id in &drawn {
+            if let (Some(line), Some(buffer)) = (self.document.get(*id), self.lines.get_mut(id)) {
+                buffer.prepare(line, px_size, &self.device);
+            }
+        }
+        if let (Some(line), Some(buffer)) = (&self.s_line, &mut self.s_buffer) {
+            buffer.prepare(line, px_size, &self.device);
+        }
+        for (line, buffer) in &mut self.overlay {
+            buffer.prepare(line, px_size, &self.device);
+        }
+
         let mut encoder = self
             .device
             .create_command_encoder(&wgpu::CommandEncoderDescriptor {
@@ -269,10 +726,10 @@ impl AsCanvas for Canvas {
                         resolve_target: None,
                         ops: wgpu::Operations {
                             load: wgpu::LoadOp::Clear(wgpu::Color {
-                                r: 1.,
-                                g: 1.,
-                                b: 1.,
-                                a: 1.0,
+                                r: self.document.settings.background[0] as f64,
+                                g: self.document.settings.background[1] as f64,
+                                b: self.document.settings.background[2] as f64,
+                                a: self.document.settings.background[3] as f64,
                             }),
                             store: true,
                         },
@@ -282,72 +739,551 @@ impl AsCanvas for Canvas {
             });
             render_pass.set_bind_group(0, &self.camera_bind_group, &[]);
             render_pass.set_pipeline(&self.render_pipeline); // 2.
-            for (_, line) in &self.lines {
+            for id in &drawn {
+                if let Some(line) = self.lines.get(id) {
+                    line.draw_self(&mut render_pass);
+                }
+            }
+            if let Some(line) = &self.s_buffer {
                 line.draw_self(&mut render_pass);
             }
+            for (_, buffer) in &self.overlay {
+                buffer.draw_self(&mut render_pass);
+            }
         }
         // submit will accept anything that implements IntoIter
         self.queue.submit(std::iter::once(encoder.finish()));
         output.present();
+        self.redraw = false;
         Ok(())
     }
 
+    fn update(&mut self, dt: Duration) {
+        let motion = match self.motion.take() {
+            Some(motion) => motion,
+            None => return,
+        };
+        let motion = match motion {
+            animation::Motion::Transition {
+                origin,
+                from,
+                to,
+                duration,
+                elapsed,
+            } => {
+                // The camera origin may have moved since the last frame.
+                self.camera.set_origin(origin);
+                let elapsed = elapsed + dt;
+                if elapsed >= duration {
+                    self.camera.set_vm(to);
+                    None
+                } else {
+                    let t = animation::ease_in_out(elapsed.as_secs_f32() / duration.as_secs_f32());
+                    self.camera.set_vm(animation::interpolate(&from, &to, t));
+                    Some(animation::Motion::Transition {
+                        origin,
+                        from,
+                        to,
+                        duration,
+                        elapsed,
+                    })
+                }
+            }
+            animation::Motion::Fling { velocity } => {
+                let dt = dt.as_secs_f32();
+                self.camera.pan(velocity * dt, self.size);
+                let velocity = velocity * (-animation::FRICTION * dt).exp();
+                (velocity.magnitude() >= animation::MIN_SPEED)
+                    .then_some(animation::Motion::Fling { velocity })
+            }
+        };
+        self.update_camera();
+        self.motion = motion;
+    }
+
+    fn needs_redraw(&self) -> bool {
+        self.redraw || self.motion.is_some()
+    }
+
     fn push_point(&mut self, mut pt: point::Point) {
-        let o_pt = camera::untransform_point(&self.camera.vm, &pt.pos);
+        pt.pos = self.to_line(&pt.pos);
+        if let Some(line) = self.s_line.as_mut() {
+            line.push_point(pt);
+        }
+        self.update_s_buffer();
+    }
+
+    fn start_line(&mut self, mut pt: point::Point) {
+        let o_pt = self.camera.untransform_point(&pt.pos);
         pt.pos = o_pt;
-        self.s_line.as_mut().unwrap().push_point(pt);
-        self.lines.insert(
-            self.next_id,
-            line::LineBuffer::new(self.s_line.as_ref().unwrap(), self),
+        self.s_line = Some(
+            line::Line::new(pt)
+                .with_origin(self.camera.origin())
+                .with_width_mode(self.width_mode),
         );
+        self.update_s_buffer();
     }
 
-    fn start_line(&mut self, mut pt: point::Point) {
-        let o_pt = camera::untransform_point(&self.camera.vm, &pt.pos);
+    fn push_sample(&mut self, mut pt: point::Point, sample: line::Sample) {
+        pt.pos = self.to_line(&pt.pos);
+        if let Some(line) = self.s_line.as_mut() {
+            line.push_sample(pt, sample);
+        }
+        self.update_s_buffer();
+    }
+
+    fn start_sampled_line(&mut self, mut pt: point::Point, sample: line::Sample) {
+        let o_pt = self.camera.untransform_point(&pt.pos);
         pt.pos = o_pt;
-        self.s_line = Some(line::Line::new(pt));
+        self.s_line = Some(
+            line::Line::with_sample(pt, sample)
+                .with_origin(self.camera.origin())
+                .with_width_mode(self.width_mode),
+        );
+        self.update_s_buffer();
+    }
+
+    fn start_pen_line(
+        &mut self,
+        pen: &point::Pen,
+        screen_px: cgmath::Point2<f32>,
+        sample: line::Sample,
+    ) {
+        let force = sample.force;
+        if let Some(pt) = pen.px2point(screen_px.x, screen_px.y, force, &self.camera, self.size) {
+            self.start_sampled_line(pt, sample);
+        }
+    }
+
+    fn push_pen_point(
+        &mut self,
+        pen: &point::Pen,
+        screen_px: cgmath::Point2<f32>,
+        sample: line::Sample,
+    ) {
+        let force = sample.force;
+        if let Some(pt) = pen.px2point(screen_px.x, screen_px.y, force, &self.camera, self.size) {
+            self.push_sample(pt, sample);
+        }
     }
 
     fn end_line(&mut self) {
-        self.s_line = None;
-        self.next_id += 1;
+        if let Some(line) = self.s_line.take() {
+            let buffer = self
+                .s_buffer
+                .take()
+                .unwrap_or_else(|| line::LineBuffer::new(&line, self));
+            let step = history::Step::insert(&mut self.document, usize::MAX, line);
+            self.lines.insert(step.id(), buffer);
+            self.history.push(vec![step]);
+        }
+        self.s_buffer = None;
+        self.redraw = true;
     }
 
     fn cancle_line(&mut self) {
-        self.lines.remove(&self.next_id);
+        self.s_line = None;
+        self.s_buffer = None;
+        self.redraw = true;
     }
 
     fn set_aspect(&mut self, aspect: f32) {
         self.camera.set_aspect(aspect);
-        self.camera_uniform.update(&self.camera);
-        self.queue.write_buffer(
-            &self.camera_buffer,
-            0,
-            bytemuck::cast_slice(&[self.camera_uniform]),
-        );
+        self.update_camera();
     }
 
     fn clear(&mut self) {
+        self.document.clear();
+        self.history.clear();
         self.lines.clear();
+        self.reset_selection();
+        self.redraw = true;
     }
 
     fn move_content(&mut self, x: f32, y: f32, z: f32) {
-        self.camera.vm = cgmath::Matrix4::from_translation(Vector3::new(x, y, z)) * self.camera.vm;
-        self.camera_uniform.update(&self.camera);
-        self.queue.write_buffer(
-            &self.camera_buffer,
-            0,
-            bytemuck::cast_slice(&[self.camera_uniform]),
-        );
+        self.camera
+            .transform(cgmath::Matrix4::from_translation(Vector3::new(x, y, z)));
+        self.update_camera();
     }
 
     fn scacle(&mut self, x: f32, y: f32, z: f32) {
-        self.camera.vm = cgmath::Matrix4::from_nonuniform_scale(x, y, z) * self.camera.vm;
-        self.camera_uniform.update(&self.camera);
-        self.queue.write_buffer(
-            &self.camera_buffer,
-            0,
-            bytemuck::cast_slice(&[self.camera_uniform]),
-        );
+        self.camera
+            .transform(cgmath::Matrix4::from_nonuniform_scale(x, y, z));
+        self.update_camera();
+    }
+
+    fn rotate_content(&mut self, angle: cgmath::Rad<f32>, pivot: cgmath::Point3<f32>) {
+        self.camera.rotate(angle, pivot);
+        self.update_camera();
+    }
+
+    fn reset_rotation(&mut self, pivot: cgmath::Point3<f32>) {
+        self.rotate_content(-self.camera.rotation(), pivot);
+    }
+
+    fn fit_to_content(&mut self, padding: f32) {
+        if let Some(rect) = self.document.bounds() {
+            self.fit_to_rect(&rect.expand(padding as f64));
+        }
+    }
+
+    fn fit_to_rect(&mut self, rect: &geometry::Rect<f64>) {
+        self.camera.fit(rect);
+        self.update_camera();
+    }
+
+    fn animate_zoom_at(&mut self, screen_px: cgmath::Point2<f32>, factor: f32, duration: Duration) {
+        let ndc = camera::px_to_ndc(screen_px, self.size);
+        if let Some(pivot) = self.camera.anchor(ndc.x, ndc.y) {
+            let mut target = self.camera.clone();
+            target.zoom(factor, pivot);
+            self.animate_to(target.origin(), *target.vm(), duration);
+        }
+    }
+
+    fn animate_pan(&mut self, delta: cgmath::Vector2<f32>, duration: Duration) {
+        let mut target = self.camera.clone();
+        target.pan(delta, self.size);
+        self.animate_to(target.origin(), *target.vm(), duration);
+    }
+
+    fn fling(&mut self, velocity: cgmath::Vector2<f32>) {
+        self.motion = Some(animation::Motion::Fling { velocity });
+    }
+
+    fn stop_motion(&mut self) {
+        self.motion = None;
+    }
+
+    fn add_bookmark(&mut self, name: &str) {
+        self.document
+            .bookmarks
+            .insert(name.to_string(), self.camera.state());
+    }
+
+    fn remove_bookmark(&mut self, name: &str) -> bool {
+        self.document.bookmarks.remove(name).is_some()
+    }
+
+    fn go_to_bookmark(&mut self, name: &str, duration: Duration) -> bool {
+        let state = match self.document.bookmarks.get(name) {
+            Some(state) => state.clone(),
+            None => return false,
+        };
+        // Only the view matrix is animated.
+        self.camera.set_origin(state.origin.into());
+        let vm = *self.camera.vm();
+        self.camera.set_state(&state);
+        self.camera.set_vm(vm);
+        self.animate_to(state.origin.into(), state.vm.into(), duration);
+        true
+    }
+
+    fn get_limits(&self) -> &camera::Limits {
+        &self.limits
+    }
+
+    fn set_limits(&mut self, limits: camera::Limits) {
+        self.limits = limits.normalized();
+        self.update_camera();
+    }
+
+    fn get_projection(&self) -> camera::Projection {
+        self.camera.projection()
+    }
+
+    fn screen_to_world(&self, px: cgmath::Point2<f32>) -> Option<cgmath::Point3<f64>> {
+        self.camera.screen_to_world(px, self.size)
+    }
+
+    fn world_to_screen(&self, pt: cgmath::Point3<f64>) -> cgmath::Point2<f32> {
+        self.camera.world_to_screen(pt, self.size)
+    }
+
+    fn set_projection(&mut self, projection: camera::Projection) {
+        self.camera.set_projection(projection);
+        self.update_camera();
+    }
+
+    fn get_width_mode(&self) -> line::WidthMode {
+        self.width_mode
+    }
+
+    fn set_width_mode(&mut self, width_mode: line::WidthMode) {
+        self.width_mode = width_mode;
+    }
+
+    fn hit_test(&self, screen_px: cgmath::Point2<f32>, tolerance: f32) -> Vec<document::StrokeId> {
+        let pos = match self.to_world(screen_px) {
+            Some(pos) => pos,
+            None => return Vec::new(),
+        };
+        let px_size = self.px_size();
+        self.selection.hit_test(
+            &self.document,
+            &self.selection_transform,
+            pos,
+            tolerance * px_size,
+            px_size,
+        )
+    }
+
+    fn get_eraser_mode(&self) -> eraser::EraserMode {
+        self.eraser_mode
+    }
+
+    fn set_eraser_mode(&mut self, mode: eraser::EraserMode) {
+        self.eraser_mode = mode;
+    }
+
+    fn start_erase(&mut self, screen_px: cgmath::Point2<f32>, radius: f32) {
+        self.commit_transform();
+        self.end_erase();
+        if let Some(pos) = self.to_world(screen_px) {
+            self.eraser = Some(eraser::Eraser::new(pos, radius, self.eraser_mode));
+            self.erase_to(screen_px);
+        }
+    }
+
+    fn erase_to(&mut self, screen_px: cgmath::Point2<f32>) {
+        let pos = match self.to_world(screen_px) {
+            Some(pos) => pos,
+            None => return,
+        };
+        let px_size = self.px_size();
+        if let Some(eraser) = self.eraser.as_mut() {
+            let ids = eraser.move_to(&mut self.document, pos, px_size);
+            self.sync_buffers(&ids);
+        }
+    }
+
+    fn end_erase(&mut self) {
+        if let Some(eraser) = self.eraser.take() {
+            self.history.push(eraser.finish());
+        }
+    }
+
+    fn select_lasso(&mut self, polygon: &[cgmath::Point2<f32>]) -> &selection::Selection {
+        self.commit_transform();
+        let polygon: Option<Vec<_>> = polygon.iter().map(|px| self.to_world(*px)).collect();
+        self.selection = polygon
+            .map(|polygon| selection::Selection::lasso(&self.document, &polygon))
+            .unwrap_or_default();
+        self.update_overlay();
+        &self.selection
+    }
+
+    fn select_rect(
+        &mut self,
+        corner0: cgmath::Point2<f32>,
+        corner1: cgmath::Point2<f32>,
+    ) -> &selection::Selection {
+        self.select_lasso(&[
+            corner0,
+            cgmath::Point2::new(corner1.x, corner0.y),
+            corner1,
+            cgmath::Point2::new(corner0.x, corner1.y),
+        ])
+    }
+
+    fn get_selection(&self) -> &selection::Selection {
+        &self.selection
+    }
+
+    fn clear_selection(&mut self) {
+        self.commit_transform();
+        self.reset_selection();
+    }
+
+    fn selection_handles(&self) -> Vec<(selection::Handle, cgmath::Point2<f32>)> {
+        let rect = match self.selection.bounds(&self.document) {
+            Some(rect) => rect,
+            None => return Vec::new(),
+        };
+        selection::handles(&rect, &self.selection_transform)
+            .into_iter()
+            .map(|(handle, pt)| {
+                let pt = cgmath::Point3::new(pt.x, pt.y, 0.);
+                (handle, self.camera.world_to_screen(pt, self.size))
+            })
+            .collect()
+    }
+
+    fn move_selection(&mut self, delta: cgmath::Vector2<f32>) {
+        let origin = cgmath::Point2::new(0., 0.);
+        if let (Some(from), Some(to)) = (self.to_world(origin), self.to_world(origin + delta)) {
+            self.transform_selection(cgmath::Matrix3::from_translation(to - from));
+        }
+    }
+
+    fn scale_selection(&mut self, factor: f32, pivot: cgmath::Point2<f32>) {
+        if let Some(pivot) = self.to_world(pivot) {
+            let pivot = pivot.to_vec();
+            self.transform_selection(
+                cgmath::Matrix3::from_translation(pivot)
+                    * cgmath::Matrix3::from_scale(factor as f64)
+                    * cgmath::Matrix3::from_translation(-pivot),
+            );
+        }
+    }
+
+    fn rotate_selection(&mut self, angle: cgmath::Rad<f32>, pivot: cgmath::Point2<f32>) {
+        if let Some(pivot) = self.to_world(pivot) {
+            let pivot = pivot.to_vec();
+            self.transform_selection(
+                cgmath::Matrix3::from_translation(pivot)
+                    * cgmath::Matrix3::from_angle_z(cgmath::Rad(angle.0 as f64))
+                    * cgmath::Matrix3::from_translation(-pivot),
+            );
+        }
+    }
+
+    fn commit_transform(&mut self) {
+        let transform =
+            std::mem::replace(&mut self.selection_transform, cgmath::Matrix3::identity());
+        if transform == cgmath::Matrix3::identity() {
+            return;
+        }
+        let ids: Vec<_> = self.selection.ids().iter().copied().collect();
+        let mut change = history::Change::new();
+        for id in &ids {
+            if let Some(line) = self
+                .document
+                .get(*id)
+                .map(|line| line.transformed(&transform))
+            {
+                change.extend(
+                    history::Step::replace(&mut self.document, *id, line)
+                        .into_iter()
+                        .flatten(),
+                );
+            }
+        }
+        self.history.push(change);
+        self.sync_buffers(&ids);
+        self.update_overlay();
+    }
+
+    fn cancel_transform(&mut self) {
+        self.set_selection_transform(cgmath::Matrix3::identity());
+    }
+
+    fn undo(&mut self) -> bool {
+        self.end_erase();
+        self.commit_transform();
+        match self.history.undo(&mut self.document) {
+            Some(ids) => {
+                self.sync_buffers(&ids);
+                true
+            }
+            None => false,
+        }
+    }
+
+    fn redo(&mut self) -> bool {
+        self.end_erase();
+        self.commit_transform();
+        match self.history.redo(&mut self.document) {
+            Some(ids) => {
+                self.sync_buffers(&ids);
+                true
+            }
+            None => false,
+        }
+    }
+
+    fn zoom_at(&mut self, screen_px: cgmath::Point2<f32>, factor: f32) {
+        let ndc = camera::px_to_ndc(screen_px, self.size);
+        if let Some(pivot) = self.camera.anchor(ndc.x, ndc.y) {
+            self.camera.zoom(factor, pivot);
+            self.update_camera();
+        }
+    }
+
+    fn rotate_at(&mut self, screen_px: cgmath::Point2<f32>, angle: cgmath::Rad<f32>) {
+        let ndc = camera::px_to_ndc(screen_px, self.size);
+        if let Some(pivot) = self.camera.anchor(ndc.x, ndc.y) {
+            self.rotate_content(angle, pivot);
+        }
+    }
+
+    fn save(&self, format: file::Format) -> io::Result<Vec<u8>> {
+        file::NativeFile::new(&self.document, &self.camera.state()).encode(format)
+    }
+
+    fn load(&mut self, data: &[u8]) -> io::Result<()> {
+        let (document, camera) = file::NativeFile::decode(data)?.into_document();
+        self.cancle_line();
+        self.end_erase();
+        self.document = document;
+        self.history.clear();
+        self.reset_selection();
+        self.camera.set_state(&camera);
+        self.update_camera();
+        self.rebuild_buffers();
+        Ok(())
+    }
+
+    fn export_svg(&self, view_box: svg::ViewBox) -> String {
+        let document = self.document.resolve(self.px_size());
+        let rect = match view_box {
+            svg::ViewBox::Camera => self.camera.visible_rect(0.),
+            svg::ViewBox::Content { padding } => {
+                document.bounds().map(|rect| rect.expand(padding as f64))
+            }
+        }
+        .unwrap_or(geometry::Rect::around((0., 0.).into(), 1.));
+        svg::export_svg(&document, &rect)
+    }
+
+    fn import_svg(&mut self, text: &str) -> io::Result<Vec<u64>> {
+        let lines = svg::import_svg(text, self.px_size() / 2.)?;
+        Ok(self.add_lines(lines))
+    }
+
+    fn export_inkml(&self) -> String {
+        inkml::export_inkml(&self.document.resolve(self.px_size()))
+    }
+
+    fn import_inkml(&mut self, text: &str) -> io::Result<Vec<u64>> {
+        let lines = inkml::import_inkml(text)?;
+        Ok(self.add_lines(lines))
+    }
+
+    fn import_xopp(&mut self, data: &[u8]) -> io::Result<Vec<u64>> {
+        let top = self
+            .document
+            .pages
+            .iter()
+            .map(|page| page.rect.min.y - xopp::PAGE_GAP)
+            .fold(0., f32::min);
+        let (pages, lines) = xopp::import_xopp(data, top)?;
+        self.document.pages.extend(pages);
+        Ok(self.add_lines(lines))
+    }
+
+    fn export_xopp(&self) -> io::Result<Vec<u8>> {
+        xopp::export_xopp(&self.document.resolve(self.px_size()))
+    }
+
+    fn export_plot(
+        &self,
+        config: &plotter::PlotterConfig,
+        dialect: plotter::Dialect,
+    ) -> io::Result<plotter::Plot> {
+        plotter::export_plot(&self.document.resolve(self.px_size()), config, dialect)
+    }
+
+    fn export_pdf(&self, regions: &[geometry::Rect<f64>], scale: f32) -> Vec<u8> {
+        let document = self.document.resolve(self.px_size());
+        if !regions.is_empty() {
+            return pdf::export_pdf(&document, regions, scale);
+        }
+        let regions: Vec<_> = if document.pages.is_empty() {
+            document.bounds().into_iter().collect()
+        } else {
+            document.pages.iter().map(|page| page.rect.cast()).collect()
+        };
+        pdf::export_pdf(&document, &regions, scale)
     }
 }
@@ -1,70 +1,69 @@
 mod structs;
 
 use cgmath::*;
-use std::f32::consts::PI;
+use serde::{Deserialize, Serialize};
+use std::{collections::BTreeMap, f32::consts::PI};
 
 use wgpu::{util::DeviceExt, Buffer, RenderPass};
 
-use crate::{point::Point, Canvas};
+use crate::{
+    geometry::{
+        point_in_polygon, segments_intersect, sweep_distance, tapered_segment_in_capsule, Contour,
+        Rect,
+    },
+    point::Point,
+    Canvas,
+};
 
-fn point_v_to_vertex_v(point_v: &std::vec::Vec<Point>) -> std::vec::Vec<structs::Vertex> {
+/// Largest distance in pixels between a tessellated circle and the real one.
+const TOLERANCE: f32 = 0.25;
+
+/// Levels of detail kept around the current one, on each side.
+const CACHED_LEVELS: i32 = 2;
+
+/// Triangles of the points, with circles fine enough for pixels of `px_size`
+/// world units.
+fn point_v_to_vertex_v(point_v: &[Point], px_size: f32) -> std::vec::Vec<structs::Vertex> {
     let mut vertex_v = std::vec::Vec::new();
     for i in 0..point_v.len() {
-        point_to_vertex(&mut vertex_v, &point_v[i]);
+        point_to_vertex(&mut vertex_v, &point_v[i], px_size);
         if i > 0 {
-            let r1 = point_v[(i - 1) as usize].width;
-            let r2 = point_v[i as usize].width;
-            let delta = r2 - r1;
-            let o_p = &point_v[(i - 1) as usize].pos;
-            let o1_p = &point_v[i as usize].pos;
-            let v = o1_p - o_p;
-            let l = v.magnitude();
-            let x_v = v.normalize();
-            let y_v = x_v.cross(Vector3 {
-                x: 0.,
-                y: 0.,
-                z: -1.,
-            });
-            let c_a = -delta / l;
-            let s_a = (l * l - delta * delta).sqrt() / l;
-            let v1 = x_v * c_a + y_v * s_a;
-            let v2 = x_v * c_a - y_v * s_a;
-            let a_p = o_p + v1 * r1;
-            let b_p = o1_p + v1 * r2;
-            let c_p = o_p + v2 * r1;
-            let d_p = o1_p + v2 * r2;
+            let [a_p, b_p, c_p, d_p] = match tangent_quad(&point_v[i - 1], &point_v[i]) {
+                Some(quad) => quad,
+                None => continue,
+            };
             vertex_v.push(structs::Vertex {
                 pos: a_p.into(),
-                color: point_v[(i - 1) as usize].color,
+                color: point_v[i - 1].color,
             });
             vertex_v.push(structs::Vertex {
                 pos: c_p.into(),
-                color: point_v[(i - 1) as usize].color,
+                color: point_v[i - 1].color,
             });
             vertex_v.push(structs::Vertex {
                 pos: b_p.into(),
-                color: point_v[i as usize].color,
+                color: point_v[i].color,
             });
             vertex_v.push(structs::Vertex {
                 pos: b_p.into(),
-                color: point_v[i as usize].color,
+                color: point_v[i].color,
             });
             vertex_v.push(structs::Vertex {
                 pos: c_p.into(),
-                color: point_v[(i - 1) as usize].color,
+                color: point_v[i - 1].color,
             });
             vertex_v.push(structs::Vertex {
                 pos: d_p.into(),
-                color: point_v[i as usize].color,
+                color: point_v[i].color,
             });
         }
     }
     vertex_v
 }
 
-fn point_to_vertex(vertex_v: &mut std::vec::Vec<structs::Vertex>, point: &Point) {
+fn point_to_vertex(vertex_v: &mut std::vec::Vec<structs::Vertex>, point: &Point, px_size: f32) {
     let width = point.width;
-    let num = get_number(width);
+    let num = get_number(width / px_size);
     let unit = 2. * PI / (num as f32);
     for i in 0..num {
         let alpha = i as f32 * unit;
@@ -92,65 +91,776 @@ fn point_to_vertex(vertex_v: &mut std::vec::Vec<structs::Vertex>, point: &Point)
     }
 }
 
-fn get_number(width: f32) -> u32 {
-    let num = (width.sqrt().sqrt() * 12.) as u32;
-    1 << {
-        if num < 2 {
-            2
-        } else if num > 12 {
-            12
-        } else {
-            num
-        }
-    }
+/// Segments of a circle of `radius` pixels, so that its chords stay within
+/// `TOLERANCE` of it.
+fn get_number(radius: f32) -> u32 {
+    // The sagitta of a chord is about `radius * (PI / num)^2 / 2`.
+    let num = PI * (radius.max(0.) / (2. * TOLERANCE)).sqrt();
+    (num.ceil() as u32).clamp(4, 256).next_power_of_two()
+}
+
+/// Level of detail for pixels of `px_size` world units: tessellating for
+/// pixels of `2^level` is fine enough.
+fn get_level(px_size: f32) -> i32 {
+    px_size.log2().floor().clamp(-64., 64.) as i32
+}
+
+/// Whether a line of `width` pixels tessellated for pixels of `built` world
+/// units still draws within `TOLERANCE` of its width with pixels of `px_size`.
+fn width_fits(width: f32, built: f32, px_size: f32) -> bool {
+    width * (built / px_size - 1.).abs() <= TOLERANCE
 }
 
 // Public
-pub use structs::Vertex;
+pub use structs::{Instance, Vertex};
 
-pub struct LineBuffer {
+/// Corners `[a, b, c, d]` of the quad joining the circles of two points along
+/// their outer tangents: `a`, `c` on the first circle and `b`, `d` on the second.
+///
+/// `None` when one circle contains the other.
+pub fn tangent_quad(p0: &Point, p1: &Point) -> Option<[Point3<f32>; 4]> {
+    let r1 = p0.width;
+    let r2 = p1.width;
+    let delta = r2 - r1;
+    let o_p = &p0.pos;
+    let o1_p = &p1.pos;
+    let v = o1_p - o_p;
+    let l = v.magnitude();
+    if l <= delta.abs() {
+        return None;
+    }
+    let x_v = v.normalize();
+    let y_v = x_v.cross(Vector3 {
+        x: 0.,
+        y: 0.,
+        z: -1.,
+    });
+    let c_a = -delta / l;
+    let s_a = (l * l - delta * delta).sqrt() / l;
+    let v1 = x_v * c_a + y_v * s_a;
+    let v2 = x_v * c_a - y_v * s_a;
+    Some([o_p + v1 * r1, o1_p + v1 * r2, o_p + v2 * r1, o1_p + v2 * r2])
+}
+
+struct Level {
     vertex_buffer: Buffer,
     count: u32,
+    /// Pixel size the level is tessellated for.
+    px_size: f32,
+}
+
+impl Level {
+    fn new(points: &[Point], px_size: f32, device: &wgpu::Device) -> Self {
+        let vertices = point_v_to_vertex_v(points, px_size);
+        let vertex_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("Vertex Buffer"),
+            contents: bytemuck::cast_slice(vertices.as_slice()),
+            usage: wgpu::BufferUsages::VERTEX,
+        });
+        Self {
+            vertex_buffer,
+            count: vertices.len() as u32,
+            px_size,
+        }
+    }
+}
+
+/// GPU data of a line, tessellated for the current zoom by `prepare`, only
+/// when it is drawn.
+///
+/// Lines in world widths keep the levels of detail near the current one, so
+/// zooming back and forth reuses them.
+pub struct LineBuffer {
+    instance_buffer: Buffer,
+    /// World transform drawn on top of the points, until baked into them.
+    transform: Matrix3<f64>,
+    levels: BTreeMap<i32, Level>,
+    /// Level drawn, set by the last `prepare`.
+    level: i32,
 }
 
 impl LineBuffer {
     pub fn new(line: &Line, canvas: &Canvas) -> Self {
-        let points = point_v_to_vertex_v(&line.points);
-        let vertex_buffer = canvas
+        let instance_buffer = canvas
             .device
             .create_buffer_init(&wgpu::util::BufferInitDescriptor {
-                label: Some("Vertex Buffer"),
-                contents: bytemuck::cast_slice(points.as_slice()),
-                usage: wgpu::BufferUsages::VERTEX,
+                label: Some("Instance Buffer"),
+                contents: bytemuck::cast_slice(&[Self::instance(
+                    line,
+                    &Matrix3::identity(),
+                    canvas,
+                )]),
+                usage: wgpu::BufferUsages::VERTEX | wgpu::BufferUsages::COPY_DST,
             });
-        let count = points.len() as u32;
         Self {
-            vertex_buffer,
-            count,
+            instance_buffer,
+            transform: Matrix3::identity(),
+            levels: BTreeMap::new(),
+            level: 0,
+        }
+    }
+
+    /// Tessellate the line for pixels of `px_size` world units, unless a
+    /// level fits already. Call before drawing it.
+    pub fn prepare(&mut self, line: &Line, px_size: f32, device: &wgpu::Device) {
+        self.level = get_level(px_size);
+        match line.width_mode {
+            WidthMode::World => {
+                let level = self.level;
+                self.levels
+                    .retain(|other, _| (other - level).abs() <= CACHED_LEVELS);
+                self.levels
+                    .entry(level)
+                    .or_insert_with(|| Level::new(&line.points, 2f32.powi(level), device));
+            }
+            WidthMode::Screen => {
+                // Zooming a little keeps the widths close enough.
+                let width = line.points.iter().map(|pt| pt.width).fold(0., f32::max);
+                if let Some((level, built)) = self.levels.iter().next() {
+                    if width_fits(width, built.px_size, px_size) {
+                        self.level = *level;
+                        return;
+                    }
+                }
+                self.levels.clear();
+                self.levels.insert(
+                    self.level,
+                    Level::new(&line.resolve(px_size).points, px_size, device),
+                );
+            }
+        }
+    }
+
+    /// Follow a move of the camera origin.
+    pub fn update(&self, line: &Line, canvas: &Canvas) {
+        canvas.queue.write_buffer(
+            &self.instance_buffer,
+            0,
+            bytemuck::cast_slice(&[Self::instance(line, &self.transform, canvas)]),
+        );
+    }
+
+    /// Draw the line moved by the world transform `transform`, without
+    /// tessellating it again. Takes effect on the next `update`.
+    pub fn set_transform(&mut self, transform: Matrix3<f64>) {
+        self.transform = transform;
+    }
+
+    fn instance(line: &Line, transform: &Matrix3<f64>, canvas: &Canvas) -> Instance {
+        let origin = transform.transform_point(Point2::from_vec(line.origin));
+        let offset = origin - Point2::from_vec(canvas.camera.origin());
+        let t = transform;
+        Instance {
+            model: [
+                [t.x.x as f32, t.x.y as f32, 0., 0.],
+                [t.y.x as f32, t.y.y as f32, 0., 0.],
+                [0., 0., 1., 0.],
+                [offset.x as f32, offset.y as f32, 0., 1.],
+            ],
         }
     }
 
+    /// Draw the level of the last `prepare`, if any.
     pub fn draw_self<'a, 'b>(&'a self, render_pass: &mut RenderPass<'b>)
     where
         'a: 'b,
     {
-        render_pass.set_vertex_buffer(0, self.vertex_buffer.slice(..));
-        render_pass.draw(0..self.count, 0..1); // 3.
+        let level = match self.levels.get(&self.level) {
+            Some(level) => level,
+            None => return,
+        };
+        render_pass.set_vertex_buffer(0, level.vertex_buffer.slice(..));
+        render_pass.set_vertex_buffer(1, self.instance_buffer.slice(..));
+        render_pass.draw(0..level.count, 0..1); // 3.
     }
 }
 
+/// Unit of the point widths of a line.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum WidthMode {
+    /// World units, the line scaling with the zoom.
+    #[default]
+    World,
+    /// Screen pixels, the same at any zoom.
+    Screen,
+}
+
+/// Pen state recorded with a point.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct Sample {
+    /// Milliseconds, from any origin chosen by the host.
+    pub time: f64,
+    /// Pressure in `[0, 1]`.
+    pub force: f32,
+}
+
+/// Point positions are relative to `origin`, so that lines far from the world
+/// origin keep their precision.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
 pub struct Line {
+    origin: Vector2<f64>,
+    width_mode: WidthMode,
     points: std::vec::Vec<Point>,
+    /// One per point, or empty when not every point has one.
+    samples: std::vec::Vec<Sample>,
 }
 
 impl Line {
     pub fn new(point: Point) -> Self {
         Self {
+            origin: Vector2::zero(),
+            width_mode: WidthMode::World,
+            points: std::vec![point],
+            samples: std::vec::Vec::new(),
+        }
+    }
+
+    pub fn with_sample(point: Point, sample: Sample) -> Self {
+        Self {
+            origin: Vector2::zero(),
+            width_mode: WidthMode::World,
             points: std::vec![point],
+            samples: std::vec![sample],
+        }
+    }
+
+    pub fn from_points(points: std::vec::Vec<Point>) -> Self {
+        Self {
+            origin: Vector2::zero(),
+            width_mode: WidthMode::World,
+            points,
+            samples: std::vec::Vec::new(),
+        }
+    }
+
+    /// Samples are dropped unless there is one per point.
+    pub fn from_samples(points: std::vec::Vec<Point>, mut samples: std::vec::Vec<Sample>) -> Self {
+        if samples.len() != points.len() {
+            samples.clear();
+        }
+        Self {
+            origin: Vector2::zero(),
+            width_mode: WidthMode::World,
+            points,
+            samples,
+        }
+    }
+
+    pub fn with_width_mode(mut self, width_mode: WidthMode) -> Self {
+        self.width_mode = width_mode;
+        self
+    }
+
+    pub fn width_mode(&self) -> WidthMode {
+        self.width_mode
+    }
+
+    /// Copy with world widths, for pixels of `px_size` world units.
+    pub fn resolve(&self, px_size: f32) -> Line {
+        let mut line = self.clone();
+        if line.width_mode == WidthMode::Screen {
+            for pt in &mut line.points {
+                pt.width *= px_size;
+            }
+            line.width_mode = WidthMode::World;
+        }
+        line
+    }
+
+    /// Make the point positions relative to `origin`.
+    pub fn with_origin(mut self, origin: Vector2<f64>) -> Self {
+        self.origin = origin;
+        self
+    }
+
+    /// World position the point positions are relative to.
+    pub fn origin(&self) -> Vector2<f64> {
+        self.origin
+    }
+
+    /// Positions relative to [`Line::origin`].
+    pub fn points(&self) -> &[Point] {
+        &self.points
+    }
+
+    /// The points with world positions, rounded far from the world origin.
+    pub fn world_points(&self) -> std::vec::Vec<Point> {
+        self.points_from(Vector2::zero())
+    }
+
+    /// The points with positions relative to the world position `origin`,
+    /// exact when it is close to them.
+    pub fn points_from(&self, origin: Vector2<f64>) -> std::vec::Vec<Point> {
+        let shift = self.origin - origin;
+        self.points
+            .iter()
+            .map(|pt| Point {
+                pos: Point3::new(
+                    (pt.pos.x as f64 + shift.x) as f32,
+                    (pt.pos.y as f64 + shift.y) as f32,
+                    pt.pos.z,
+                ),
+                ..pt.clone()
+            })
+            .collect()
+    }
+
+    /// One per point, or empty.
+    pub fn samples(&self) -> &[Sample] {
+        &self.samples
+    }
+
+    /// Contours whose union is the tessellated shape, for each run of points
+    /// sharing a color, relative to the world position `origin`. A segment
+    /// takes the color of its first point.
+    ///
+    /// Widths count as world units, see [`Line::resolve`] for screen widths.
+    pub fn outline(
+        &self,
+        origin: Vector2<f64>,
+    ) -> std::vec::Vec<([f32; 4], std::vec::Vec<Contour>)> {
+        let mut runs: std::vec::Vec<([f32; 4], std::vec::Vec<Contour>)> = std::vec::Vec::new();
+        let points = self.points_from(origin);
+        for (i, pt) in points.iter().enumerate() {
+            match runs.last_mut() {
+                Some((color, _)) if *color == pt.color => (),
+                _ => runs.push((pt.color, std::vec::Vec::new())),
+            }
+            let contours = &mut runs.last_mut().unwrap().1;
+            contours.push(Contour::Circle {
+                center: Point2::new(pt.pos.x, pt.pos.y),
+                radius: pt.width,
+            });
+            let quad = match points.get(i + 1).and_then(|next| tangent_quad(pt, next)) {
+                Some([a_p, b_p, c_p, d_p]) => [a_p, b_p, d_p, c_p].map(|p| Point2::new(p.x, p.y)),
+                None => continue,
+            };
+            let area: f32 = (0..4)
+                .map(|i| {
+                    let (p, q) = (quad[i], quad[(i + 1) % 4]);
+                    p.x * q.y - q.x * p.y
+                })
+                .sum();
+            contours.push(Contour::Quad(if area < 0. {
+                [quad[3], quad[2], quad[1], quad[0]]
+            } else {
+                quad
+            }));
+        }
+        runs
+    }
+
+    /// Signed distance in the xy plane from the world point `pos` to the line,
+    /// negative inside, for pixels of `px_size` world units.
+    pub fn distance(&self, pos: Point2<f64>, px_size: f32) -> Option<f32> {
+        self.sweep_distance(pos, pos, px_size)
+    }
+
+    /// Like [`Line::distance`], from the closest point of the world segment
+    /// `[from, to]`.
+    pub fn sweep_distance(&self, from: Point2<f64>, to: Point2<f64>, px_size: f32) -> Option<f32> {
+        let a = self.to_local(from);
+        let b = self.to_local(to);
+        let scale = match self.width_mode {
+            WidthMode::World => 1.,
+            WidthMode::Screen => px_size,
+        };
+        let center = |pt: &Point| Point2::new(pt.pos.x, pt.pos.y);
+        let first = self.points.first()?;
+        let distance = sweep_distance(
+            a,
+            b,
+            center(first),
+            first.width * scale,
+            center(first),
+            first.width * scale,
+        );
+        Some(self.points.windows(2).fold(distance, |distance, w| {
+            distance.min(sweep_distance(
+                a,
+                b,
+                center(&w[0]),
+                w[0].width * scale,
+                center(&w[1]),
+                w[1].width * scale,
+            ))
+        }))
+    }
+
+    /// Copy moved by the world transform `transform`, keeping the origin.
+    /// Widths follow its scale, screen ones too, as when drawn through a
+    /// [`LineBuffer`] transform.
+    pub fn transformed(&self, transform: &Matrix3<f64>) -> Line {
+        let origin = Point2::from_vec(self.origin);
+        let scale = transform.determinant().abs().sqrt() as f32;
+        let mut line = self.clone();
+        for pt in &mut line.points {
+            let world = origin + Vector2::new(pt.pos.x as f64, pt.pos.y as f64);
+            let pos = transform.transform_point(world) - origin;
+            pt.pos.x = pos.x as f32;
+            pt.pos.y = pos.y as f32;
+            pt.width *= scale;
+        }
+        line
+    }
+
+    /// Whether the line has a point inside the closed world polygon
+    /// `polygon`, or crosses its border. Widths are left out.
+    pub fn meets_polygon(&self, polygon: &[Point2<f64>]) -> bool {
+        let polygon: std::vec::Vec<_> = polygon.iter().map(|pt| self.to_local(*pt)).collect();
+        let center = |pt: &Point| Point2::new(pt.pos.x, pt.pos.y);
+        if self
+            .points
+            .iter()
+            .any(|pt| point_in_polygon(center(pt), &polygon))
+        {
+            return true;
+        }
+        self.points.windows(2).any(|w| {
+            (0..polygon.len()).any(|i| {
+                segments_intersect(
+                    center(&w[0]),
+                    center(&w[1]),
+                    polygon[i],
+                    polygon[(i + 1) % polygon.len()],
+                )
+            })
+        })
+    }
+
+    /// Pieces left after erasing what the line covers within `radius` of the
+    /// world segment `[from, to]`, for pixels of `px_size` world units.
+    /// Segments are cut where their outline leaves it. `None` when nothing is
+    /// erased.
+    pub fn erase(
+        &self,
+        from: Point2<f64>,
+        to: Point2<f64>,
+        radius: f32,
+        px_size: f32,
+    ) -> Option<std::vec::Vec<Line>> {
+        let a = self.to_local(from);
+        let b = self.to_local(to);
+        let scale = match self.width_mode {
+            WidthMode::World => 1.,
+            WidthMode::Screen => px_size,
+        };
+        let center = |i: usize| Point2::new(self.points[i].pos.x, self.points[i].pos.y);
+        let width = |i: usize| self.points[i].width * scale;
+        let cut = |i: usize, j: usize| {
+            tapered_segment_in_capsule(center(i), center(j), width(i), width(j), a, b, radius)
+        };
+        let inside = |i: usize| cut(i, i).is_some();
+        if self.points.len() == 1 {
+            return inside(0).then(std::vec::Vec::new);
+        }
+
+        let mut erased = false;
+        let mut pieces = std::vec::Vec::new();
+        let mut points = std::vec::Vec::new();
+        let mut samples = std::vec::Vec::new();
+        // Point at `t` of the way from the point `i` to the next one.
+        let mut push = |points: &mut std::vec::Vec<Point>, i: usize, t: f32| {
+            if t == 0. {
+                points.push(self.points[i].clone());
+                samples.extend(self.samples.get(i).copied());
+            } else {
+                points.push(self.points[i].lerp(&self.points[i + 1], t));
+                if let (Some(s0), Some(s1)) = (self.samples.get(i), self.samples.get(i + 1)) {
+                    samples.push(Sample {
+                        time: s0.time + (s1.time - s0.time) * t as f64,
+                        force: s0.force + (s1.force - s0.force) * t,
+                    });
+                }
+            }
+        };
+        for i in 0..self.points.len() {
+            if !inside(i) {
+                push(&mut points, i, 0.);
+            }
+            if i + 1 == self.points.len() {
+                break;
+            }
+            if let Some((t0, t1)) = cut(i, i + 1) {
+                erased = true;
+                if t0 > 0. {
+                    push(&mut points, i, t0);
+                }
+                pieces.push(std::mem::take(&mut points));
+                if t1 < 1. {
+                    push(&mut points, i, t1);
+                }
+            }
+        }
+        if !erased {
+            return None;
         }
+        pieces.push(points);
+        Some(self.pieces(pieces, samples))
+    }
+
+    /// Lines like this one made of `pieces`, dropping the single points left
+    /// by cuts. `samples` holds those of all the pieces in a row, or none.
+    fn pieces(
+        &self,
+        pieces: std::vec::Vec<std::vec::Vec<Point>>,
+        mut samples: std::vec::Vec<Sample>,
+    ) -> std::vec::Vec<Line> {
+        let mut lines = std::vec::Vec::new();
+        for points in pieces {
+            let rest = samples.split_off(points.len().min(samples.len()));
+            let piece_samples = std::mem::replace(&mut samples, rest);
+            if points.len() < 2 {
+                continue;
+            }
+            lines.push(
+                Line::from_samples(points, piece_samples)
+                    .with_origin(self.origin)
+                    .with_width_mode(self.width_mode),
+            );
+        }
+        lines
+    }
+
+    fn to_local(&self, pos: Point2<f64>) -> Point2<f32> {
+        Point2::new(
+            (pos.x - self.origin.x) as f32,
+            (pos.y - self.origin.y) as f32,
+        )
+    }
+
+    /// World area covered by the line in the xy plane, widths included
+    /// unless in screen pixels.
+    pub fn bounds(&self) -> Option<Rect<f64>> {
+        let local = self
+            .points
+            .iter()
+            .map(|pt| {
+                let width = match self.width_mode {
+                    WidthMode::World => pt.width,
+                    WidthMode::Screen => 0.,
+                };
+                Rect::around(Point2::new(pt.pos.x, pt.pos.y), width)
+            })
+            .reduce(|a, b| a.union(&b))?
+            .cast::<f64>();
+        Some(Rect::new(local.min + self.origin, local.max + self.origin))
     }
 
+    /// Drops the samples, the new point having none.
     pub fn push_point(&mut self, point: Point) {
         self.points.push(point);
+        self.samples.clear();
+    }
+
+    pub fn push_sample(&mut self, point: Point, sample: Sample) {
+        if self.samples.len() == self.points.len() {
+            self.samples.push(sample);
+        }
+        self.points.push(point);
+    }
+}
+
+/// Black line through `xy`, of constant width `width`, for tests.
+#[cfg(test)]
+pub(crate) fn test_line(xy: &[(f32, f32)], width: f32) -> Line {
+    Line::from_points(
+        xy.iter()
+            .map(|(x, y)| Point {
+                pos: [*x, *y, 0.].into(),
+                color: [0., 0., 0., 1.],
+                width,
+            })
+            .collect(),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_distance() {
+        let line = Line::from_points(std::vec![
+            Point {
+                pos: [0., 0., 0.].into(),
+                color: [0., 0., 0., 1.],
+                width: 1.,
+            },
+            Point {
+                pos: [10., 0., 0.].into(),
+                color: [0., 0., 0., 1.],
+                width: 3.,
+            },
+            Point {
+                pos: [10., 1., 0.].into(),
+                color: [0., 0., 0., 1.],
+                width: 0.5,
+            },
+        ])
+        .with_origin(Vector2::new(1e7, -1e7));
+        let distance = |x: f64, y: f64| line.distance(Point2::new(1e7 + x, y - 1e7), 1.).unwrap();
+        assert!((distance(-3., 0.) - 2.).abs() < 1e-5);
+        assert!((distance(10., 5.) - 2.).abs() < 1e-5);
+        assert!((distance(5., 0.) + 2.).abs() < 1e-5);
+        // The tapered side is tilted by `asin(0.2)`.
+        let expected = 4. * (1. - 0.04f32).sqrt() - 2.;
+        assert!((distance(5., 4.) - expected).abs() < 1e-5);
+
+        let sweep = |x0: f64, y0: f64, x1: f64, y1: f64| {
+            line.sweep_distance(
+                Point2::new(1e7 + x0, y0 - 1e7),
+                Point2::new(1e7 + x1, y1 - 1e7),
+                1.,
+            )
+            .unwrap()
+        };
+        assert!((sweep(-5., 5., -5., -5.) - 4.).abs() < 1e-4);
+        assert!((sweep(-5., 0., 20., 0.) + 3.).abs() < 1e-4);
+        assert!((sweep(-3., 0., -3., 0.) - 2.).abs() < 1e-5);
+
+        let line = line.with_width_mode(WidthMode::Screen);
+        assert!((line.distance(Point2::new(1e7 - 3., -1e7), 0.5).unwrap() - 2.5).abs() < 1e-5);
+    }
+
+    #[test]
+    fn test_transformed() {
+        let line = Line::from_points(std::vec![
+            Point {
+                pos: [1., 0., 0.5].into(),
+                color: [0., 0., 0., 1.],
+                width: 1.,
+            },
+            Point {
+                pos: [2., 1., 0.5].into(),
+                color: [0., 0., 0., 1.],
+                width: 2.,
+            },
+        ])
+        .with_origin(Vector2::new(1e7, 0.));
+        let pivot = Vector2::new(1e7, 0.);
+        let transform = Matrix3::from_translation(pivot)
+            * Matrix3::from_angle_z(Deg(90.))
+            * Matrix3::from_scale(2.)
+            * Matrix3::from_translation(-pivot);
+        let moved = line.transformed(&transform);
+        assert_eq!(moved.origin(), line.origin());
+        let points = moved.points();
+        assert_abs_diff_eq!(points[0].pos, Point3::new(0., 2., 0.5), epsilon = 1e-6);
+        assert_abs_diff_eq!(points[1].pos, Point3::new(-2., 4., 0.5), epsilon = 1e-6);
+        assert_abs_diff_eq!(points[1].width, 4., epsilon = 1e-6);
+        let moved = line.with_width_mode(WidthMode::Screen).transformed(
+            &(Matrix3::from_translation(Vector2::new(0.5, 0.)) * Matrix3::from_scale(3.)),
+        );
+        assert_eq!(moved.width_mode(), WidthMode::Screen);
+        assert_eq!(moved.points()[1].width, 6.);
+    }
+
+    #[test]
+    fn test_get_number() {
+        assert_eq!(get_number(0.), 4);
+        assert_eq!(get_number(1.), 8);
+        assert_eq!(get_number(100.), 64);
+        assert_eq!(get_number(1e6), 256);
+        for radius in [0.5, 2., 10., 50., 500.] {
+            let num = get_number(radius) as f32;
+            assert!(num >= 256. || radius * (1. - (PI / num).cos()) <= TOLERANCE);
+        }
+        assert_eq!(get_level(1.), 0);
+        assert_eq!(get_level(0.3), -2);
+        assert_eq!(get_level(5.), 2);
+
+        assert!(width_fits(2., 1., 1.1));
+        assert!(!width_fits(2., 1., 2.));
+        assert!(width_fits(0.5, 1., 2.));
+    }
+
+    #[test]
+    fn test_resolve() {
+        let line = Line::from_points(std::vec![
+            Point {
+                pos: [0., 0., 0.].into(),
+                color: [0., 0., 0., 1.],
+                width: 2.,
+            },
+            Point {
+                pos: [10., 0., 0.].into(),
+                color: [0., 0., 0., 1.],
+                width: 3.,
+            },
+        ])
+        .with_width_mode(WidthMode::Screen);
+        assert_eq!(
+            line.bounds(),
+            Some(Rect::new(Point2::new(0., 0.), Point2::new(10., 0.)))
+        );
+        let resolved = line.resolve(0.5);
+        assert_eq!(resolved.width_mode(), WidthMode::World);
+        let widths: std::vec::Vec<_> = resolved.points().iter().map(|pt| pt.width).collect();
+        assert_eq!(widths, [1., 1.5]);
+        assert_eq!(
+            resolved.bounds(),
+            Some(Rect::new(Point2::new(-1., -1.5), Point2::new(11.5, 1.5)))
+        );
+    }
+
+    #[test]
+    fn test_bounds_far() {
+        let line = Line::from_points(std::vec![Point {
+            pos: [0.013, 0., 0.].into(),
+            color: [0., 0., 0., 1.],
+            width: 0.,
+        }])
+        .with_origin(Vector2::new(1e6, 0.));
+        let bounds = line.bounds().unwrap();
+        assert_eq!(bounds.min.x, 1e6 + 0.013f32 as f64);
+        assert_eq!(bounds.max.x, bounds.min.x);
+    }
+
+    #[test]
+    fn test_erase_edge() {
+        let line = Line::from_points(std::vec![
+            Point {
+                pos: [0., 0., 0.].into(),
+                color: [0., 0., 0., 1.],
+                width: 2.,
+            },
+            Point {
+                pos: [10., 0., 0.].into(),
+                color: [0., 0., 0., 1.],
+                width: 2.,
+            },
+        ]);
+        // Away from the centerline, over the outline.
+        let eraser = Point2::new(5., 2.5);
+        let pieces = line.erase(eraser, eraser, 1., 1.).unwrap();
+        assert_eq!(pieces.len(), 2);
+        let half = (3f32 * 3. - 2.5 * 2.5).sqrt();
+        assert_abs_diff_eq!(pieces[0].points()[1].pos.x, 5. - half, epsilon = 1e-4);
+        assert_abs_diff_eq!(pieces[1].points()[0].pos.x, 5. + half, epsilon = 1e-4);
+        assert!(line
+            .erase(Point2::new(5., 3.5), Point2::new(5., 3.5), 1., 1.)
+            .is_none());
+
+        // Screen widths are pixels.
+        let line = line.with_width_mode(WidthMode::Screen);
+        assert!(line.erase(eraser, eraser, 1., 0.5).is_none());
+        assert!(line.erase(eraser, eraser, 1., 1.).is_some());
+    }
+
+    #[test]
+    fn test_one_point() {
+        let line = Line::new(Point {
+            pos: [1., 2., 0.].into(),
+            color: [0., 0., 0., 1.],
+            width: 1.,
+        });
+        let vertex_v = point_v_to_vertex_v(line.points(), 0.1);
+        assert!(!vertex_v.is_empty());
+        assert_eq!(vertex_v.len() % 3, 0);
+        for vertex in &vertex_v {
+            let (dx, dy) = (vertex.pos[0] - 1., vertex.pos[1] - 2.);
+            assert!((dx * dx + dy * dy).sqrt() <= 1. + 1e-5);
+        }
     }
 }
@@ -0,0 +1,162 @@
+use std::collections::BTreeMap;
+
+use cgmath::EuclideanSpace;
+use pdf_writer::{Content, Name, Pdf, Ref};
+
+use crate::{
+    document::Document,
+    geometry::{Contour, Rect},
+    point,
+};
+
+/// Control point distance of the cubic quarter circle, per unit radius.
+const KAPPA: f32 = 0.552_284_8;
+
+/// Render the document as a vector PDF with one page per world area in
+/// `regions`, at `scale` PDF points per world unit.
+pub fn export_pdf(document: &Document, regions: &[Rect<f64>], scale: f32) -> Vec<u8> {
+    let mut next_ref = 1;
+    let mut alloc = || {
+        next_ref += 1;
+        Ref::new(next_ref - 1)
+    };
+    let catalog_id = alloc();
+    let page_tree_id = alloc();
+
+    // One graphics state per alpha, shared by all pages.
+    let mut alpha_v = BTreeMap::new();
+    let colors = std::iter::once(document.settings.background).chain(
+        document
+            .iter()
+            .flat_map(|(_, line)| line.points().iter().map(|pt| pt.color)),
+    );
+    for color in colors {
+        alpha_v.entry(alpha_key(&color)).or_insert_with(&mut alloc);
+    }
+
+    let mut pdf = Pdf::new();
+    let mut page_ids = Vec::new();
+    for region in regions {
+        let page_id = alloc();
+        let content_id = alloc();
+        page_ids.push(page_id);
+
+        // Contents are relative to the corner of the region.
+        let (width, height) = (region.width() as f32, region.height() as f32);
+        let mut content = Content::new();
+        content.transform([scale, 0., 0., scale, 0., 0.]);
+        let background = document.settings.background;
+        if background[3] > 0. {
+            set_fill(&mut content, &background);
+            content.rect(0., 0., width, height);
+            content.fill_nonzero();
+        }
+        for (_, line) in document.iter() {
+            if !line.bounds().is_some_and(|rect| rect.intersects(region)) {
+                continue;
+            }
+            for (color, contours) in line.outline(region.min.to_vec()) {
+                set_fill(&mut content, &color);
+                for contour in contours {
+                    write_contour(&mut content, &contour);
+                }
+                content.fill_nonzero();
+            }
+        }
+        pdf.stream(content_id, &content.finish());
+
+        let mut page = pdf.page(page_id);
+        page.parent(page_tree_id)
+            .media_box(pdf_writer::Rect::new(0., 0., width * scale, height * scale))
+            .contents(content_id);
+        let names: Vec<_> = alpha_v.keys().map(|key| gs_name(*key)).collect();
+        page.resources().ext_g_states().pairs(
+            names
+                .iter()
+                .map(|name| Name(name.as_bytes()))
+                .zip(alpha_v.values().copied()),
+        );
+    }
+
+    for (key, gs_id) in &alpha_v {
+        pdf.ext_graphics(*gs_id)
+            .non_stroking_alpha(*key as f32 / 255.);
+    }
+    pdf.pages(page_tree_id)
+        .count(page_ids.len() as i32)
+        .kids(page_ids);
+    pdf.catalog(catalog_id).pages(page_tree_id);
+    pdf.finish()
+}
+
+fn alpha_key(color: &[f32; 4]) -> u8 {
+    (color[3].clamp(0., 1.) * 255.).round() as u8
+}
+
+fn gs_name(key: u8) -> String {
+    format!("A{}", key)
+}
+
+fn set_fill(content: &mut Content, color: &[f32; 4]) {
+    content.set_parameters(Name(gs_name(alpha_key(color)).as_bytes()));
+    content.set_fill_rgb(
+        point::linear_to_srgb(color[0]),
+        point::linear_to_srgb(color[1]),
+        point::linear_to_srgb(color[2]),
+    );
+}
+
+fn write_contour(content: &mut Content, contour: &Contour) {
+    match contour {
+        Contour::Circle { center, radius } => {
+            let (x, y, r) = (center.x, center.y, *radius);
+            let k = r * KAPPA;
+            content.move_to(x + r, y);
+            content.cubic_to(x + r, y + k, x + k, y + r, x, y + r);
+            content.cubic_to(x - k, y + r, x - r, y + k, x - r, y);
+            content.cubic_to(x - r, y - k, x - k, y - r, x, y - r);
+            content.cubic_to(x + k, y - r, x + r, y - k, x + r, y);
+            content.close_path();
+        }
+        Contour::Quad(corners) => {
+            content.move_to(corners[0].x, corners[0].y);
+            for pt in &corners[1..] {
+                content.line_to(pt.x, pt.y);
+            }
+            content.close_path();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{line::Line, point::Point};
+
+    #[test]
+    fn test_export() {
+        let mut document = Document::new();
+        document.push(Line::from_points(vec![
+            Point {
+                pos: [0., 0., 0.].into(),
+                color: [1., 0., 0., 0.5],
+                width: 0.1,
+            },
+            Point {
+                pos: [1., 1., 0.].into(),
+                color: [1., 0., 0., 0.5],
+                width: 0.2,
+            },
+        ]));
+        let regions = [
+            Rect::new((0., 0.).into(), (1., 1.).into()),
+            Rect::new((5., 5.).into(), (6., 7.).into()),
+        ];
+        let pdf = export_pdf(&document, &regions, 72.);
+        let text = String::from_utf8_lossy(&pdf);
+        assert!(text.starts_with("%PDF-"));
+        assert!(text.contains("/Count 2"));
+        assert!(text.contains("/MediaBox [0 0 72 144]"));
+        assert!(text.contains("/ca 0.5"));
+    }
+}
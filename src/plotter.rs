@@ -0,0 +1,317 @@
+use std::{
+    fmt::Write,
+    io::{self, Error},
+    time::Duration,
+};
+
+use cgmath::{EuclideanSpace, MetricSpace, Point2};
+
+use crate::document::Document;
+
+/// HPGL plotter units per millimeter.
+const HPGL_PER_MM: f32 = 40.;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Dialect {
+    GCode,
+    Hpgl,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Units {
+    Millimeters,
+    Inches,
+}
+
+impl Units {
+    fn to_mm(self, value: f32) -> f32 {
+        match self {
+            Units::Millimeters => value,
+            Units::Inches => value * 25.4,
+        }
+    }
+}
+
+/// Lengths and rates are in `units`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct PlotterConfig {
+    pub units: Units,
+    /// Width and height of the drawable area, from the origin.
+    pub bed_size: (f32, f32),
+    /// Bed units per world unit, `None` to fit the content on the bed.
+    pub scale: Option<f32>,
+    /// Drawing speed, per minute.
+    pub feed_rate: f32,
+    /// Pen up speed, per minute.
+    pub travel_rate: f32,
+    /// G-code lifting the pen.
+    pub pen_up: String,
+    /// G-code lowering the pen.
+    pub pen_down: String,
+    /// Time the pen takes to go up or down.
+    pub pen_delay: Duration,
+    /// Whether strokes may be drawn backwards to shorten travel.
+    pub reverse: bool,
+}
+
+impl Default for PlotterConfig {
+    fn default() -> Self {
+        Self {
+            units: Units::Millimeters,
+            bed_size: (210., 297.),
+            scale: None,
+            feed_rate: 1000.,
+            travel_rate: 3000.,
+            pen_up: "G0 Z1".to_string(),
+            pen_down: "G0 Z0".to_string(),
+            pen_delay: Duration::from_millis(200),
+            reverse: true,
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Plot {
+    pub program: String,
+    /// Pen down distance, in bed units.
+    pub draw_distance: f32,
+    /// Pen up distance, in bed units.
+    pub travel_distance: f32,
+    pub estimated_time: Duration,
+}
+
+/// Plot the centerlines of the strokes, starting and ending at the origin.
+///
+/// Strokes are ordered, and reversed when allowed, by always going to the
+/// closest remaining end. Fails when a rate is not positive.
+pub fn export_plot(
+    document: &Document,
+    config: &PlotterConfig,
+    dialect: Dialect,
+) -> io::Result<Plot> {
+    for (name, rate) in [
+        ("feed rate", config.feed_rate),
+        ("travel rate", config.travel_rate),
+    ] {
+        if rate.is_nan() || rate <= 0. {
+            return Err(Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("{} {} is not positive", name, rate),
+            ));
+        }
+    }
+    let paths = plan(document, config);
+    let origin = Point2::new(0., 0.);
+    let mut draw_distance = 0.;
+    let mut travel_distance = 0.;
+    let mut current = origin;
+    for path in &paths {
+        travel_distance += current.distance(path[0]);
+        draw_distance += path.windows(2).map(|w| w[0].distance(w[1])).sum::<f32>();
+        current = *path.last().unwrap();
+    }
+    travel_distance += current.distance(origin);
+
+    let program = match dialect {
+        Dialect::GCode => gcode(&paths, config),
+        Dialect::Hpgl => hpgl(&paths, config),
+    };
+    let minutes = draw_distance / config.feed_rate + travel_distance / config.travel_rate;
+    let moving = Duration::try_from_secs_f32(minutes * 60.)
+        .map_err(|e| Error::new(io::ErrorKind::InvalidInput, e))?;
+    Ok(Plot {
+        program,
+        draw_distance,
+        travel_distance,
+        estimated_time: moving + config.pen_delay * (2 * paths.len() as u32),
+    })
+}
+
+/// Polylines in bed units, in plotting order.
+fn plan(document: &Document, config: &PlotterConfig) -> Vec<Vec<Point2<f32>>> {
+    let bounds = match document.bounds() {
+        Some(bounds) => bounds,
+        None => return Vec::new(),
+    };
+    let scale = config.scale.unwrap_or_else(|| {
+        let fit = (config.bed_size.0 / bounds.width() as f32)
+            .min(config.bed_size.1 / bounds.height() as f32);
+        if fit.is_finite() {
+            fit
+        } else {
+            1.
+        }
+    });
+    let mut remaining: Vec<Vec<Point2<f32>>> = document
+        .iter()
+        .map(|(_, line)| {
+            line.points_from(bounds.min.to_vec())
+                .iter()
+                .map(|pt| Point2::new(pt.pos.x * scale, pt.pos.y * scale))
+                .collect::<Vec<_>>()
+        })
+        .filter(|path| !path.is_empty())
+        .collect();
+    for path in &mut remaining {
+        for pt in path.iter_mut() {
+            if pt.x > config.bed_size.0 || pt.y > config.bed_size.1 {
+                log::warn!("clamped a point outside of the bed: {:?}", pt);
+                pt.x = pt.x.min(config.bed_size.0);
+                pt.y = pt.y.min(config.bed_size.1);
+            }
+        }
+    }
+
+    let mut paths = Vec::with_capacity(remaining.len());
+    let mut current = Point2::new(0., 0.);
+    while !remaining.is_empty() {
+        let (i, reversed, _) = remaining
+            .iter()
+            .enumerate()
+            .flat_map(|(i, path)| {
+                let start = (i, false, current.distance2(path[0]));
+                let end = (i, true, current.distance2(*path.last().unwrap()));
+                std::iter::once(start).chain(config.reverse.then_some(end))
+            })
+            .min_by(|a, b| a.2.total_cmp(&b.2))
+            .unwrap();
+        let mut path = remaining.swap_remove(i);
+        if reversed {
+            path.reverse();
+        }
+        current = *path.last().unwrap();
+        paths.push(path);
+    }
+    paths
+}
+
+fn gcode(paths: &[Vec<Point2<f32>>], config: &PlotterConfig) -> String {
+    let mut program = String::new();
+    let _ = writeln!(
+        program,
+        "{}\nG90\n{}",
+        match config.units {
+            Units::Millimeters => "G21",
+            Units::Inches => "G20",
+        },
+        config.pen_up
+    );
+    for path in paths {
+        let _ = writeln!(
+            program,
+            "G0 X{:.3} Y{:.3} F{}\n{}",
+            path[0].x, path[0].y, config.travel_rate, config.pen_down
+        );
+        for pt in &path[1..] {
+            let _ = writeln!(
+                program,
+                "G1 X{:.3} Y{:.3} F{}",
+                pt.x, pt.y, config.feed_rate
+            );
+        }
+        let _ = writeln!(program, "{}", config.pen_up);
+    }
+    let _ = writeln!(program, "G0 X0 Y0 F{}", config.travel_rate);
+    program
+}
+
+fn hpgl(paths: &[Vec<Point2<f32>>], config: &PlotterConfig) -> String {
+    let to_hpgl = |value: f32| (config.units.to_mm(value) * HPGL_PER_MM).round() as i32;
+    let mut program = String::from("IN;SP1;");
+    // Velocity in centimeters per second.
+    let _ = write!(
+        program,
+        "VS{:.1};",
+        config.units.to_mm(config.feed_rate) / 600.
+    );
+    for path in paths {
+        let _ = write!(
+            program,
+            "PU{},{};PD",
+            to_hpgl(path[0].x),
+            to_hpgl(path[0].y)
+        );
+        for (i, pt) in path.iter().enumerate() {
+            if i > 0 {
+                program.push(',');
+            }
+            let _ = write!(program, "{},{}", to_hpgl(pt.x), to_hpgl(pt.y));
+        }
+        program.push(';');
+    }
+    program.push_str("PU0,0;SP0;\n");
+    program
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::line::{test_line, Line};
+
+    fn line(xy: &[(f32, f32)]) -> Line {
+        test_line(xy, 0.)
+    }
+
+    #[test]
+    fn test_order() {
+        let mut document = Document::new();
+        document.push(line(&[(10., 0.), (20., 0.)]));
+        document.push(line(&[(5., 0.), (0., 0.)]));
+        let config = PlotterConfig {
+            scale: Some(1.),
+            ..Default::default()
+        };
+        let plot = export_plot(&document, &config, Dialect::GCode).unwrap();
+        assert_eq!(plot.draw_distance, 15.);
+        assert_eq!(plot.travel_distance, 25.);
+        assert!(plot
+            .program
+            .starts_with("G21\nG90\nG0 Z1\nG0 X0.000 Y0.000 F3000\nG0 Z0\nG1 X5.000"));
+        assert_eq!(
+            plot.estimated_time,
+            Duration::from_secs_f32((15. / 1000. + 25. / 3000.) * 60.) + Duration::from_millis(800)
+        );
+
+        let plot = export_plot(
+            &document,
+            &PlotterConfig {
+                reverse: false,
+                ..config
+            },
+            Dialect::Hpgl,
+        )
+        .unwrap();
+        assert_eq!(plot.travel_distance, 35.);
+        assert_eq!(
+            plot.program,
+            "IN;SP1;VS1.7;PU200,0;PD200,0,0,0;PU400,0;PD400,0,800,0;PU0,0;SP0;\n"
+        );
+    }
+
+    #[test]
+    fn test_bad_rate() {
+        let mut document = Document::new();
+        document.push(line(&[(0., 0.), (1., 0.)]));
+        for config in [
+            PlotterConfig {
+                feed_rate: 0.,
+                ..Default::default()
+            },
+            PlotterConfig {
+                travel_rate: -1.,
+                ..Default::default()
+            },
+            PlotterConfig {
+                feed_rate: f32::NAN,
+                ..Default::default()
+            },
+        ] {
+            assert_eq!(
+                export_plot(&document, &config, Dialect::GCode)
+                    .unwrap_err()
+                    .kind(),
+                io::ErrorKind::InvalidInput
+            );
+        }
+    }
+}
@@ -1,12 +1,59 @@
+use cgmath::Transform;
+use serde::{Deserialize, Serialize};
 use winit::dpi::PhysicalSize;
 
-#[derive(Clone, Debug, PartialEq)]
+use crate::camera::{self, Camera};
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
 pub struct Point {
     pub pos: cgmath::Point3<f32>,
     pub color: [f32; 4],
     pub width: f32,
 }
 
+impl Point {
+    /// Point at `t` of the way to `other`, blending the position, the color
+    /// and the width.
+    pub fn lerp(&self, other: &Point, t: f32) -> Point {
+        let mut color = self.color;
+        for (c, o) in color.iter_mut().zip(other.color) {
+            *c += (o - *c) * t;
+        }
+        Point {
+            pos: self.pos + (other.pos - self.pos) * t,
+            color,
+            width: self.width + (other.width - self.width) * t,
+        }
+    }
+}
+
+/// Encode a linear color channel for an sRGB output.
+pub fn linear_to_srgb(c: f32) -> f32 {
+    let c = c.clamp(0., 1.);
+    if c <= 0.0031308 {
+        c * 12.92
+    } else {
+        1.055 * c.powf(1. / 2.4) - 0.055
+    }
+}
+
+/// Decode an sRGB channel into the linear color stored in points.
+pub fn srgb_to_linear(c: f32) -> f32 {
+    let c = c.clamp(0., 1.);
+    if c <= 0.04045 {
+        c / 12.92
+    } else {
+        ((c + 0.055) / 1.055).powf(2.4)
+    }
+}
+
+/// `#rrggbb` of the sRGB encoded color, ignoring alpha.
+pub fn srgb_hex(color: &[f32; 4]) -> String {
+    let [r, g, b] =
+        [color[0], color[1], color[2]].map(|c| (linear_to_srgb(c) * 255.).round() as u8);
+    format!("#{:02x}{:02x}{:02x}", r, g, b)
+}
+
 #[derive(Clone, Debug, PartialEq)]
 pub struct Pen {
     width: f32,
@@ -18,15 +65,23 @@ impl Pen {
         Self { width, color }
     }
 
-    pub fn px2point(&self, x: f32, y: f32, force: f32, sz: PhysicalSize<u32>) -> Point {
-        let unit = (sz.height as f32) / 2.;
-        let ratio = (sz.width as f32) / (sz.height as f32);
-        let point = Point {
-            pos: [x / unit - ratio, -y / unit + 1.0, 0.].into(),
+    /// Point in view of the world plane `z = 0` under the pixel `(x, y)`, as
+    /// `AsCanvas::start_line` takes it. `None` when the view is degenerate.
+    pub fn px2point(
+        &self,
+        x: f32,
+        y: f32,
+        force: f32,
+        camera: &Camera,
+        sz: PhysicalSize<u32>,
+    ) -> Option<Point> {
+        let ndc = camera::px_to_ndc(cgmath::Point2::new(x, y), sz);
+        let local = camera.ndc_to_local(ndc.x, ndc.y, 0.)?;
+        Some(Point {
+            pos: camera.vm().transform_point(local),
             color: self.color,
             width: self.width * (1.0 + force * 2.),
-        };
-        point
+        })
     }
 
     pub fn set_width(&mut self, width: f32) {
@@ -46,3 +101,40 @@ impl Default for Pen {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use cgmath::{assert_abs_diff_eq, Point2, Point3};
+
+    use super::*;
+
+    #[test]
+    fn test_px2point() {
+        let size = PhysicalSize::new(800, 600);
+        let mut camera = Camera::new(
+            (0.0, 0.0, 1.0).into(),
+            (0., 0., 0.).into(),
+            cgmath::Vector3::unit_y(),
+            800. / 600.,
+            90.0,
+            0.1,
+            100.0,
+        );
+        camera.zoom(2.5, Point3::new(0.3, -0.2, -1.));
+        camera.rotate(cgmath::Deg(30.).into(), Point3::new(-0.1, 0.4, -1.));
+        let pen = Pen::new(0.01, [1., 0., 0., 1.]);
+        let pt = pen.px2point(123., 456., 0.5, &camera, size).unwrap();
+        assert_eq!(pt.width, 0.02);
+        // The camera origin is the world origin.
+        let local = camera.untransform_point(&pt.pos).cast::<f64>().unwrap();
+        let world = camera
+            .screen_to_world(Point2::new(123., 456.), size)
+            .unwrap();
+        assert_abs_diff_eq!(local, world, epsilon = 1e-4);
+        assert_abs_diff_eq!(
+            camera.world_to_screen(local, size),
+            Point2::new(123., 456.),
+            epsilon = 1e-2
+        );
+    }
+}
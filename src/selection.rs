@@ -0,0 +1,269 @@
+use std::{cmp::Reverse, collections::BTreeSet};
+
+use cgmath::{EuclideanSpace, Matrix3, Point2, Transform};
+
+use crate::{
+    document::{Document, StrokeId},
+    geometry::Rect,
+    line::{Line, WidthMode},
+    point::Point,
+};
+
+/// Linear color of the selection box.
+pub const SELECTION_COLOR: [f32; 4] = [0.1, 0.4, 1., 1.];
+
+/// Half width of the selection box, in pixels.
+pub const SELECTION_WIDTH: f32 = 1.;
+
+/// Radius of the handles of the selection box, in pixels.
+pub const HANDLE_RADIUS: f32 = 5.;
+
+/// Grip of the selection box.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Handle {
+    /// Corner of the box, counter-clockwise from the one with the smallest
+    /// coordinates, to scale the selection.
+    Corner(usize),
+    /// Middle of the side with the largest y, to rotate the selection.
+    Rotate,
+}
+
+/// Strokes picked by the user, for later operations.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Selection {
+    ids: BTreeSet<StrokeId>,
+}
+
+impl Selection {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Lines with a point inside the closed world polygon `polygon`, or
+    /// crossing its border.
+    pub fn lasso(document: &Document, polygon: &[Point2<f64>]) -> Self {
+        let rect = polygon
+            .iter()
+            .map(|pt| Rect::around(*pt, 0.))
+            .reduce(|a, b| a.union(&b));
+        let ids = match rect {
+            Some(rect) if polygon.len() >= 3 => document
+                .query(&rect, 0.)
+                .filter(|id| {
+                    document
+                        .get(*id)
+                        .is_some_and(|line| line.meets_polygon(polygon))
+                })
+                .collect(),
+            _ => BTreeSet::new(),
+        };
+        Self { ids }
+    }
+
+    pub fn ids(&self) -> &BTreeSet<StrokeId> {
+        &self.ids
+    }
+
+    pub fn contains(&self, id: StrokeId) -> bool {
+        self.ids.contains(&id)
+    }
+
+    pub fn len(&self) -> usize {
+        self.ids.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.ids.is_empty()
+    }
+
+    /// Forget the lines no longer in `document`. Returns whether there were
+    /// any.
+    pub fn retain(&mut self, document: &Document) -> bool {
+        let len = self.ids.len();
+        self.ids.retain(|id| document.get(*id).is_some());
+        self.ids.len() != len
+    }
+
+    /// Like [`Document::hit_test`], the selected lines being moved by the
+    /// world transform `transform`.
+    pub fn hit_test(
+        &self,
+        document: &Document,
+        transform: &Matrix3<f64>,
+        pos: Point2<f64>,
+        tolerance: f32,
+        px_size: f32,
+    ) -> std::vec::Vec<StrokeId> {
+        let mut ids = document.hit_test(pos, tolerance, px_size);
+        // The index does not know where the selection is moved to.
+        ids.retain(|id| !self.contains(*id));
+        ids.extend(self.ids.iter().copied().filter(|id| {
+            document
+                .get(*id)
+                .and_then(|line| line.transformed(transform).distance(pos, px_size))
+                .is_some_and(|distance| distance <= tolerance)
+        }));
+        ids.sort_by_key(|id| Reverse(document.index_of(*id)));
+        ids
+    }
+
+    /// World area covered by the lines, widths included unless in screen
+    /// pixels.
+    pub fn bounds(&self, document: &Document) -> Option<Rect<f64>> {
+        self.ids
+            .iter()
+            .filter_map(|id| document.get(*id)?.bounds())
+            .reduce(|a, b| a.union(&b))
+    }
+}
+
+/// World positions of the handles of the box `rect`, moved by the world
+/// transform `transform`.
+pub fn handles(rect: &Rect<f64>, transform: &Matrix3<f64>) -> std::vec::Vec<(Handle, Point2<f64>)> {
+    let (min, max) = (rect.min, rect.max);
+    [
+        (Handle::Corner(0), Point2::new(min.x, min.y)),
+        (Handle::Corner(1), Point2::new(max.x, min.y)),
+        (Handle::Corner(2), Point2::new(max.x, max.y)),
+        (Handle::Corner(3), Point2::new(min.x, max.y)),
+        (Handle::Rotate, Point2::new((min.x + max.x) / 2., max.y)),
+    ]
+    .into_iter()
+    .map(|(handle, pt)| (handle, transform.transform_point(pt)))
+    .collect()
+}
+
+/// Lines drawn over a selection: its box `rect` moved by the world transform
+/// `transform`, and the handles.
+pub fn overlay(rect: &Rect<f64>, transform: &Matrix3<f64>) -> std::vec::Vec<Line> {
+    let handles = handles(rect, transform);
+    let corners: std::vec::Vec<_> = handles
+        .iter()
+        .filter(|(handle, _)| matches!(handle, Handle::Corner(_)))
+        .map(|(_, pt)| *pt)
+        .collect();
+    let point = |origin: Point2<f64>, pt: Point2<f64>, width: f32| Point {
+        pos: [(pt.x - origin.x) as f32, (pt.y - origin.y) as f32, 0.].into(),
+        color: SELECTION_COLOR,
+        width,
+    };
+    let mut lines = std::vec::Vec::new();
+    if let Some(origin) = corners.first() {
+        let border = corners
+            .iter()
+            .chain(Some(origin))
+            .map(|pt| point(*origin, *pt, SELECTION_WIDTH))
+            .collect();
+        lines.push(Line::from_points(border).with_origin(origin.to_vec()));
+    }
+    lines.extend(
+        handles
+            .iter()
+            .map(|(_, pt)| Line::new(point(*pt, *pt, HANDLE_RADIUS)).with_origin(pt.to_vec())),
+    );
+    lines
+        .into_iter()
+        .map(|line| line.with_width_mode(WidthMode::Screen))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use cgmath::SquareMatrix;
+
+    use super::*;
+    use crate::line::test_line;
+
+    fn line(xy: &[(f32, f32)]) -> Line {
+        test_line(xy, 0.5).with_origin(cgmath::Vector2::new(1e6, 0.))
+    }
+
+    #[test]
+    fn test_lasso() {
+        let mut document = Document::new();
+        document.push(line(&[(1., 1.), (2., 2.)]));
+        document.push(line(&[(-5., 3.), (5., 3.)]));
+        document.push(line(&[(4.5, 0.), (8., 0.)]));
+        document.push(line(&[(-1., -1.)]));
+        document.push(line(&[(6., 6.)]));
+        // A triangle with its corner at (1e6 + 4, 4).
+        let polygon: std::vec::Vec<_> = [(0., 0.), (4., 4.), (0., 8.)]
+            .into_iter()
+            .map(|(x, y)| Point2::new(1e6 + x, y))
+            .collect();
+        let selection = Selection::lasso(&document, &polygon);
+        let rect = selection.bounds(&document).unwrap();
+        let transform = Matrix3::from_translation(cgmath::Vector2::new(1., 2.));
+        let handles = handles(&rect, &transform);
+        assert_eq!(handles[2], (Handle::Corner(2), Point2::new(1e6 + 6.5, 5.5)));
+        assert_eq!(handles[4], (Handle::Rotate, Point2::new(1e6 + 1., 5.5)));
+        assert_eq!(overlay(&rect, &transform).len(), 6);
+
+        let mut selection = selection;
+        assert_eq!(
+            selection
+                .ids()
+                .iter()
+                .copied()
+                .collect::<std::vec::Vec<_>>(),
+            [1, 2]
+        );
+        assert_eq!(
+            selection.bounds(&document),
+            Some(Rect::new(
+                Point2::new(1e6 - 5.5, 0.5),
+                Point2::new(1e6 + 5.5, 3.5)
+            ))
+        );
+
+        document.remove(2);
+        assert!(selection.retain(&document));
+        assert!(!selection.retain(&document));
+        assert_eq!(selection.len(), 1);
+
+        let polygon: std::vec::Vec<_> = [(4., -1.), (5., -1.), (5., 1.), (4., 1.)]
+            .into_iter()
+            .map(|(x, y)| Point2::new(1e6 + x, y))
+            .collect();
+        assert!(Selection::lasso(&document, &polygon).contains(3));
+        assert!(Selection::lasso(&document, &polygon[..2]).is_empty());
+    }
+
+    #[test]
+    fn test_hit_test() {
+        let mut document = Document::new();
+        document.push(line(&[(0., 0.), (2., 0.)]));
+        document.push(line(&[(0., 4.), (2., 4.)]));
+        let polygon: std::vec::Vec<_> = [(-1., 3.), (3., 3.), (3., 5.), (-1., 5.)]
+            .into_iter()
+            .map(|(x, y)| Point2::new(1e6 + x, y))
+            .collect();
+        let selection = Selection::lasso(&document, &polygon);
+        assert_eq!(
+            selection
+                .ids()
+                .iter()
+                .copied()
+                .collect::<std::vec::Vec<_>>(),
+            [2]
+        );
+
+        // The selection is drawn over the other line.
+        let transform = Matrix3::from_translation(cgmath::Vector2::new(0., -4.));
+        let hit = |x: f64, y: f64| {
+            selection.hit_test(&document, &transform, Point2::new(1e6 + x, y), 0.1, 1.)
+        };
+        assert_eq!(hit(1., 0.), [2, 1]);
+        assert!(hit(1., 4.).is_empty());
+        assert_eq!(
+            selection.hit_test(
+                &document,
+                &Matrix3::identity(),
+                Point2::new(1e6 + 1., 4.),
+                0.1,
+                1.
+            ),
+            [2]
+        );
+    }
+}
@@ -0,0 +1,484 @@
+use std::{f64::consts::PI, fmt::Write, io};
+
+use cgmath::{EuclideanSpace, InnerSpace, Point2, Vector2};
+use svgtypes::{Paint, SimplePathSegment, SimplifyingPathParser, Transform};
+
+use crate::{
+    document::Document,
+    error::invalid_data,
+    geometry::{Contour, Rect},
+    line::Line,
+    point::{self, Point},
+};
+
+/// Which part of the world an exported image shows.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ViewBox {
+    /// What the camera currently sees on the plane `z = 0`.
+    Camera,
+    /// All strokes, with `padding` world units around them.
+    Content { padding: f32 },
+}
+
+/// Render the document as an SVG image of the world area `view_box`.
+///
+/// Strokes of constant width and color become stroked centerlines, others
+/// the filled outline of their tessellated shape. World y points up, so it is
+/// flipped, and coordinates start at the top left corner of `view_box`.
+pub fn export_svg(document: &Document, view_box: &Rect<f64>) -> String {
+    let origin = Vector2::new(view_box.min.x, view_box.max.y);
+    let (width, height) = (view_box.width() as f32, view_box.height() as f32);
+    let mut svg = String::new();
+    let _ = writeln!(
+        svg,
+        r#"<svg xmlns="http://www.w3.org/2000/svg" viewBox="0 0 {} {}">"#,
+        width, height
+    );
+    let background = document.settings.background;
+    if background[3] > 0. {
+        let _ = writeln!(
+            svg,
+            r#"<rect x="0" y="0" width="{}" height="{}" fill="{}" fill-opacity="{}"/>"#,
+            width,
+            height,
+            point::srgb_hex(&background),
+            background[3]
+        );
+    }
+    for (_, line) in document.iter() {
+        write_line(&mut svg, line, origin);
+    }
+    svg.push_str("</svg>\n");
+    svg
+}
+
+/// Write `line` with coordinates relative to the world point `origin`.
+fn write_line(svg: &mut String, line: &Line, origin: Vector2<f64>) {
+    let points = line.points_from(origin);
+    let points = &points[..];
+    let first = match points.first() {
+        Some(first) => first,
+        None => return,
+    };
+    if points
+        .iter()
+        .all(|pt| pt.width == first.width && pt.color == first.color)
+    {
+        let mut d = format!("M{} {}", first.pos.x, svg_y(first.pos.y));
+        // A single point still needs a segment to get a round dot.
+        let rest = if points.len() > 1 {
+            &points[1..]
+        } else {
+            points
+        };
+        for pt in rest {
+            let _ = write!(d, " L{} {}", pt.pos.x, svg_y(pt.pos.y));
+        }
+        let _ = writeln!(
+            svg,
+            r#"<path d="{}" fill="none" stroke="{}" stroke-opacity="{}" stroke-width="{}" stroke-linecap="round" stroke-linejoin="round"/>"#,
+            d,
+            point::srgb_hex(&first.color),
+            first.color[3],
+            first.width * 2.
+        );
+        return;
+    }
+    for (color, contours) in line.outline(origin) {
+        let mut d = String::new();
+        for contour in contours {
+            write_contour(&mut d, &contour);
+        }
+        let _ = writeln!(
+            svg,
+            r#"<path d="{}" fill="{}" fill-opacity="{}" fill-rule="nonzero"/>"#,
+            d.trim_start(),
+            point::srgb_hex(&color),
+            color[3]
+        );
+    }
+}
+
+/// Flipping y turns the contours clockwise, so circles are too.
+fn write_contour(d: &mut String, contour: &Contour) {
+    match contour {
+        Contour::Circle { center, radius } => {
+            let (x, y, r) = (center.x, svg_y(center.y), radius);
+            let _ = write!(
+                d,
+                " M{} {} A{r} {r} 0 1 0 {} {} A{r} {r} 0 1 0 {} {} Z",
+                x + r,
+                y,
+                x - r,
+                y,
+                x + r,
+                y,
+                r = r
+            );
+        }
+        Contour::Quad(corners) => {
+            for (i, pt) in corners.iter().enumerate() {
+                let _ = write!(
+                    d,
+                    " {}{} {}",
+                    if i == 0 { "M" } else { "L" },
+                    pt.x,
+                    svg_y(pt.y)
+                );
+            }
+            d.push_str(" Z");
+        }
+    }
+}
+
+/// Flip the y axis, without writing `-0`.
+fn svg_y(y: f32) -> f32 {
+    0. - y
+}
+
+/// Read the shapes of an SVG image as lines: `path`, `line`, `polyline`,
+/// `polygon`, `rect`, `circle` and `ellipse` elements, using their stroke or
+/// else their fill.
+///
+/// Curves are flattened to within `tolerance` world units. User units map to
+/// world units as in [`export_svg`].
+pub fn import_svg(text: &str, tolerance: f32) -> io::Result<Vec<Line>> {
+    let tree = roxmltree::Document::parse(text).map_err(invalid_data)?;
+    let tolerance = (tolerance as f64).max(1e-6);
+    let mut lines = Vec::new();
+    for node in tree.descendants().filter(|node| node.is_element()) {
+        if node.ancestors().any(|node| {
+            matches!(
+                node.tag_name().name(),
+                "defs" | "clipPath" | "mask" | "marker" | "pattern" | "symbol"
+            )
+        }) {
+            continue;
+        }
+        let transform = node
+            .ancestors()
+            .filter_map(|node| node.attribute("transform"))
+            .filter_map(|value| value.parse::<Transform>().ok())
+            .fold(Transform::default(), |child, parent| {
+                multiply(&parent, &child)
+            });
+        let paths = match shape_paths(node, &transform, tolerance) {
+            Some(paths) if !paths.is_empty() => paths,
+            _ => continue,
+        };
+        let (color, width) = match paint(node) {
+            Some(paint) => paint,
+            None => continue,
+        };
+        let width = width
+            * (transform.a * transform.d - transform.b * transform.c)
+                .abs()
+                .sqrt();
+        for path in paths.into_iter().filter(|path| !path.is_empty()) {
+            lines.push(Line::from_points(
+                path.into_iter()
+                    .map(|pt| Point {
+                        pos: [pt.x as f32, svg_y(pt.y as f32), 0.].into(),
+                        color,
+                        width: (width / 2.) as f32,
+                    })
+                    .collect(),
+            ));
+        }
+    }
+    Ok(lines)
+}
+
+/// Outlines of a shape element in user space, `None` for other elements.
+fn shape_paths(
+    node: roxmltree::Node,
+    transform: &Transform,
+    tolerance: f64,
+) -> Option<Vec<Vec<Point2<f64>>>> {
+    let number = |name: &str| {
+        node.attribute(name)
+            .and_then(|value| value.parse::<svgtypes::Length>().ok())
+            .map_or(0., |length| length.number)
+    };
+    let apply = |x: f64, y: f64| apply(transform, Point2::new(x, y));
+    let path = match node.tag_name().name() {
+        "line" => vec![
+            apply(number("x1"), number("y1")),
+            apply(number("x2"), number("y2")),
+        ],
+        "polyline" | "polygon" => {
+            let mut path: Vec<_> = svgtypes::PointsParser::from(node.attribute("points")?)
+                .map(|(x, y)| apply(x, y))
+                .collect();
+            if node.tag_name().name() == "polygon" && !path.is_empty() {
+                path.push(path[0]);
+            }
+            path
+        }
+        "rect" => {
+            let (x, y, w, h) = (number("x"), number("y"), number("width"), number("height"));
+            vec![
+                apply(x, y),
+                apply(x + w, y),
+                apply(x + w, y + h),
+                apply(x, y + h),
+                apply(x, y),
+            ]
+        }
+        "circle" | "ellipse" => {
+            let (rx, ry) = if node.tag_name().name() == "circle" {
+                (number("r"), number("r"))
+            } else {
+                (number("rx"), number("ry"))
+            };
+            let (cx, cy) = (number("cx"), number("cy"));
+            let r = rx.max(ry)
+                * (transform.a * transform.d - transform.b * transform.c)
+                    .abs()
+                    .sqrt();
+            let num = if r > tolerance {
+                (PI / (1. - tolerance / r).acos()).ceil() as usize
+            } else {
+                4
+            }
+            .clamp(4, 1024);
+            (0..=num)
+                .map(|i| {
+                    let alpha = 2. * PI * (i % num) as f64 / num as f64;
+                    apply(cx + rx * alpha.cos(), cy + ry * alpha.sin())
+                })
+                .collect()
+        }
+        "path" => return Some(path_data(node.attribute("d")?, transform, tolerance)),
+        _ => return None,
+    };
+    Some(vec![path])
+}
+
+fn path_data(d: &str, transform: &Transform, tolerance: f64) -> Vec<Vec<Point2<f64>>> {
+    let mut paths: Vec<Vec<Point2<f64>>> = Vec::new();
+    let mut start = Point2::new(0., 0.);
+    let mut current = start;
+    let mut closed = true;
+    for segment in SimplifyingPathParser::from(d) {
+        let segment = match segment {
+            Ok(segment) => segment,
+            Err(e) => {
+                log::warn!("stopped reading path data: {}", e);
+                break;
+            }
+        };
+        if closed && !matches!(segment, SimplePathSegment::MoveTo { .. }) {
+            paths.push(vec![start]);
+        }
+        closed = false;
+        match segment {
+            SimplePathSegment::MoveTo { x, y } => {
+                start = apply(transform, Point2::new(x, y));
+                current = start;
+                paths.push(vec![start]);
+                continue;
+            }
+            SimplePathSegment::LineTo { x, y } => {
+                current = apply(transform, Point2::new(x, y));
+                paths.last_mut().unwrap().push(current);
+            }
+            SimplePathSegment::CurveTo {
+                x1,
+                y1,
+                x2,
+                y2,
+                x,
+                y,
+            } => {
+                let p = [
+                    current,
+                    apply(transform, Point2::new(x1, y1)),
+                    apply(transform, Point2::new(x2, y2)),
+                    apply(transform, Point2::new(x, y)),
+                ];
+                let dd = ((p[0] - p[1]) - (p[1] - p[2]))
+                    .magnitude()
+                    .max(((p[1] - p[2]) - (p[2] - p[3])).magnitude());
+                let num = segment_count(0.75 * dd, tolerance);
+                let path = paths.last_mut().unwrap();
+                for i in 1..=num {
+                    let t = i as f64 / num as f64;
+                    let u = 1. - t;
+                    path.push(Point2::from_vec(
+                        p[0].to_vec() * (u * u * u)
+                            + p[1].to_vec() * (3. * u * u * t)
+                            + p[2].to_vec() * (3. * u * t * t)
+                            + p[3].to_vec() * (t * t * t),
+                    ));
+                }
+                current = p[3];
+            }
+            SimplePathSegment::Quadratic { x1, y1, x, y } => {
+                let p = [
+                    current,
+                    apply(transform, Point2::new(x1, y1)),
+                    apply(transform, Point2::new(x, y)),
+                ];
+                let dd = ((p[0] - p[1]) - (p[1] - p[2])).magnitude();
+                let num = segment_count(0.25 * dd, tolerance);
+                let path = paths.last_mut().unwrap();
+                for i in 1..=num {
+                    let t = i as f64 / num as f64;
+                    let u = 1. - t;
+                    path.push(Point2::from_vec(
+                        p[0].to_vec() * (u * u)
+                            + p[1].to_vec() * (2. * u * t)
+                            + p[2].to_vec() * (t * t),
+                    ));
+                }
+                current = p[2];
+            }
+            SimplePathSegment::ClosePath => {
+                paths.last_mut().unwrap().push(start);
+                current = start;
+                closed = true;
+            }
+        }
+    }
+    paths
+}
+
+/// Segments needed so that a curve whose second derivative is bounded by
+/// `8 * deviation` stays within `tolerance` of its chords.
+fn segment_count(deviation: f64, tolerance: f64) -> usize {
+    ((deviation / tolerance).sqrt().ceil() as usize).clamp(1, 1024)
+}
+
+/// Linear color and stroke width of a shape, `None` when it is not painted.
+fn paint(node: roxmltree::Node) -> Option<([f32; 4], f64)> {
+    let opacity: f64 = node
+        .ancestors()
+        .filter_map(|node| node.attribute("opacity"))
+        .filter_map(|value| value.parse::<svgtypes::Number>().ok())
+        .map(|number| number.0)
+        .product();
+    let (color, paint_opacity, width) = match property(node, "stroke").map(Paint::from_str) {
+        Some(Ok(Paint::Color(color))) => (
+            color,
+            property(node, "stroke-opacity"),
+            property(node, "stroke-width")
+                .and_then(|value| value.parse::<svgtypes::Length>().ok())
+                .map_or(1., |length| length.number),
+        ),
+        _ => match property(node, "fill").map(Paint::from_str) {
+            Some(Ok(Paint::Color(color))) => (color, property(node, "fill-opacity"), 1.),
+            // Missing fill defaults to black.
+            None => (svgtypes::Color::black(), property(node, "fill-opacity"), 1.),
+            _ => return None,
+        },
+    };
+    let paint_opacity = paint_opacity
+        .and_then(|value| value.parse::<svgtypes::Number>().ok())
+        .map_or(1., |number| number.0);
+    Some((
+        [
+            point::srgb_to_linear(color.red as f32 / 255.),
+            point::srgb_to_linear(color.green as f32 / 255.),
+            point::srgb_to_linear(color.blue as f32 / 255.),
+            (color.alpha as f64 / 255. * paint_opacity * opacity) as f32,
+        ],
+        width,
+    ))
+}
+
+/// Value of an inherited presentation property, from `style` or attributes.
+fn property<'a>(node: roxmltree::Node<'a, 'a>, name: &str) -> Option<&'a str> {
+    node.ancestors()
+        .filter(|node| node.is_element())
+        .find_map(|node| {
+            node.attribute("style")
+                .and_then(|style| {
+                    style.split(';').find_map(|declaration| {
+                        let (key, value) = declaration.split_once(':')?;
+                        (key.trim() == name).then(|| value.trim())
+                    })
+                })
+                .or_else(|| node.attribute(name))
+                .filter(|value| *value != "inherit")
+        })
+}
+
+fn apply(t: &Transform, pt: Point2<f64>) -> Point2<f64> {
+    Point2::new(t.a * pt.x + t.c * pt.y + t.e, t.b * pt.x + t.d * pt.y + t.f)
+}
+
+/// `t1` applied after `t2`.
+fn multiply(t1: &Transform, t2: &Transform) -> Transform {
+    Transform::new(
+        t1.a * t2.a + t1.c * t2.b,
+        t1.b * t2.a + t1.d * t2.b,
+        t1.a * t2.c + t1.c * t2.d,
+        t1.b * t2.c + t1.d * t2.d,
+        t1.a * t2.e + t1.c * t2.f + t1.e,
+        t1.b * t2.e + t1.d * t2.f + t1.f,
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn pt(x: f32, y: f32, width: f32) -> Point {
+        Point {
+            pos: [x, y, 0.].into(),
+            color: [1., 0., 0., 0.5],
+            width,
+        }
+    }
+
+    #[test]
+    fn test_export() {
+        let mut document = Document::new();
+        document.push(Line::from_points(vec![pt(0., 0., 0.1), pt(1., 1., 0.1)]));
+        document.push(Line::from_points(vec![pt(0., 0., 0.1), pt(1., 1., 0.2)]));
+        let view_box = Rect::new(Point2::new(-1., -1.), Point2::new(2., 2.));
+        let svg = export_svg(&document, &view_box);
+        assert!(svg.contains(r#"viewBox="0 0 3 3""#));
+        assert!(svg.contains(
+            r##"<path d="M1 2 L2 1" fill="none" stroke="#ff0000" stroke-opacity="0.5" stroke-width="0.2""##
+        ));
+        assert_eq!(svg.matches(r##"fill="#ff0000""##).count(), 1);
+
+        // Far from the world origin, coordinates keep their precision.
+        let far = Vector2::new(1e6, -1e6);
+        let mut moved = Document::new();
+        for (_, line) in document.iter() {
+            moved.push(line.clone().with_origin(far));
+        }
+        let view_box = Rect::new(view_box.min + far, view_box.max + far);
+        assert_eq!(export_svg(&moved, &view_box), svg);
+    }
+
+    #[test]
+    fn test_import() {
+        let text = r##"<svg xmlns="http://www.w3.org/2000/svg">
+            <defs><circle r="5"/></defs>
+            <g stroke="#ff0000" stroke-width="2" transform="translate(10 0)">
+                <path d="M0 0 C 0 10 10 10 10 0 Z M20 0 L 30 0"/>
+                <circle cx="0" cy="0" r="5" style="stroke: blue; stroke-opacity: 0.5"/>
+                <rect width="1" height="1" stroke="none"/>
+            </g>
+            <line x1="0" y1="0" x2="1" y2="1" stroke="black" fill="red"/>
+        </svg>"##;
+        let lines = import_svg(text, 0.1).unwrap();
+        assert_eq!(lines.len(), 5);
+        let curve = lines[0].points();
+        assert_eq!(curve[0].pos, [10., 0., 0.].into());
+        assert_eq!(curve[0].color, [1., 0., 0., 1.]);
+        assert_eq!(curve[0].width, 1.);
+        assert_eq!(curve.last().unwrap().pos, curve[0].pos);
+        assert!(curve.len() > 4);
+        assert_eq!(lines[1].points()[1].pos, [40., 0., 0.].into());
+        assert_eq!(lines[2].points()[0].color, [0., 0., 1., 0.5]);
+        // Filled rect without stroke.
+        assert_eq!(lines[3].points().len(), 5);
+        assert_eq!(lines[4].points()[1].pos, [1., -1., 0.].into());
+        assert!(import_svg(text, 0.01).unwrap()[0].points().len() > curve.len());
+    }
+}
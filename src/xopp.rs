@@ -0,0 +1,301 @@
+use std::{
+    fmt::Write as _,
+    io::{self, Read, Write},
+};
+
+use cgmath::{Point2, Vector2};
+use flate2::{read::GzDecoder, write::GzEncoder, Compression};
+
+use crate::{
+    document::{Background, Document, Page, Ruling},
+    error::invalid_data,
+    geometry::Rect,
+    line::Line,
+    point::{self, Point},
+};
+
+/// World distance between imported pages.
+pub const PAGE_GAP: f32 = 10.;
+
+/// Read the pages and strokes of a Xournal++ file, gzipped or not.
+///
+/// One world unit is one point. Pages are stacked downwards from the world
+/// height `top`, the y axis being flipped.
+pub fn import_xopp(data: &[u8], top: f32) -> io::Result<(Vec<Page>, Vec<Line>)> {
+    let mut text = String::new();
+    if data.starts_with(&[0x1f, 0x8b]) {
+        GzDecoder::new(data).read_to_string(&mut text)?;
+    } else {
+        text = String::from_utf8(data.to_vec()).map_err(invalid_data)?;
+    }
+    let tree = roxmltree::Document::parse(&text).map_err(invalid_data)?;
+
+    let mut pages = Vec::new();
+    let mut lines = Vec::new();
+    let mut top = top;
+    for page_node in tree
+        .root_element()
+        .children()
+        .filter(|node| node.tag_name().name() == "page")
+    {
+        let number = |name: &str| {
+            page_node
+                .attribute(name)
+                .and_then(|value| value.parse::<f32>().ok())
+                .ok_or_else(|| invalid_data(format!("page without {}", name)))
+        };
+        let (width, height) = (number("width")?, number("height")?);
+        let rect = Rect::new(Point2::new(0., top - height), Point2::new(width, top));
+        let mut background = Background::default();
+        if let Some(node) = page_node
+            .children()
+            .find(|node| node.tag_name().name() == "background")
+        {
+            if node.attribute("type") == Some("solid") {
+                if let Some(color) = node.attribute("color").and_then(parse_color) {
+                    background.color = color;
+                }
+                background.ruling = match node.attribute("style").unwrap_or("plain") {
+                    "plain" => Ruling::Plain,
+                    "lined" => Ruling::Lined,
+                    "ruled" => Ruling::Ruled,
+                    "graph" => Ruling::Graph,
+                    "dotted" => Ruling::Dotted,
+                    style => Ruling::Other(style.to_string()),
+                };
+            } else {
+                log::warn!("unsupported page background: {:?}", node.attribute("type"));
+            }
+        }
+
+        for stroke in page_node
+            .descendants()
+            .filter(|node| node.tag_name().name() == "stroke")
+        {
+            let mut color = stroke
+                .attribute("color")
+                .and_then(parse_color)
+                .unwrap_or([0., 0., 0., 1.]);
+            if stroke.attribute("tool") == Some("highlighter") && color[3] == 1. {
+                color[3] = 0.5;
+            }
+            // Nominal width, then optionally the width at each point.
+            let widths: Vec<f32> = stroke
+                .attribute("width")
+                .unwrap_or("1")
+                .split_whitespace()
+                .filter_map(|value| value.parse().ok())
+                .collect();
+            let coords: Vec<f32> = stroke
+                .text()
+                .unwrap_or_default()
+                .split_whitespace()
+                .map(|value| value.parse().map_err(invalid_data))
+                .collect::<io::Result<_>>()?;
+            let points: Vec<Point> = coords
+                .chunks_exact(2)
+                .enumerate()
+                .map(|(i, xy)| {
+                    let width = match &widths[..] {
+                        [] => 1.,
+                        [width] => *width,
+                        [_, pressures @ ..] => pressures[i.min(pressures.len() - 1)],
+                    };
+                    Point {
+                        pos: [xy[0], top - xy[1], 0.].into(),
+                        color,
+                        width: width / 2.,
+                    }
+                })
+                .collect();
+            if !points.is_empty() {
+                lines.push(Line::from_points(points));
+            }
+        }
+
+        pages.push(Page { rect, background });
+        top -= height + PAGE_GAP;
+    }
+    Ok((pages, lines))
+}
+
+/// Write the document as a gzipped Xournal++ file.
+///
+/// Each line goes to the page containing the center of its bounds, or the
+/// closest one. Without pages, a single page surrounds the content.
+pub fn export_xopp(document: &Document) -> io::Result<Vec<u8>> {
+    let mut pages = document.pages.clone();
+    if pages.is_empty() {
+        pages.push(Page {
+            rect: document.bounds().map_or(
+                Rect::new(Point2::new(0., -842.), Point2::new(595., 0.)),
+                |rect| rect.expand(PAGE_GAP as f64).cast(),
+            ),
+            background: Background {
+                color: document.settings.background,
+                ruling: Ruling::Plain,
+            },
+        });
+    }
+
+    let mut layers = vec![String::new(); pages.len()];
+    for (_, line) in document.iter() {
+        let center = match line.bounds() {
+            Some(rect) => rect.center().cast::<f32>().unwrap(),
+            None => continue,
+        };
+        let (page_i, page) = pages
+            .iter()
+            .enumerate()
+            .min_by(|(_, a), (_, b)| {
+                distance(&a.rect, center).total_cmp(&distance(&b.rect, center))
+            })
+            .unwrap();
+        write_stroke(&mut layers[page_i], line, &page.rect);
+    }
+
+    let mut xml = String::from(
+        "<?xml version=\"1.0\" standalone=\"no\"?>\n<xournal creator=\"painting\" fileversion=\"4\">\n<title>Xournal++ document</title>\n",
+    );
+    for (page, layer) in pages.iter().zip(layers) {
+        let style = match &page.background.ruling {
+            Ruling::Plain => "plain",
+            Ruling::Lined => "lined",
+            Ruling::Ruled => "ruled",
+            Ruling::Graph => "graph",
+            Ruling::Dotted => "dotted",
+            Ruling::Other(style) => style,
+        };
+        let _ = write!(
+            xml,
+            "<page width=\"{}\" height=\"{}\">\n<background type=\"solid\" color=\"{}\" style=\"{}\"/>\n<layer>\n{}</layer>\n</page>\n",
+            page.rect.width(),
+            page.rect.height(),
+            hex(&page.background.color),
+            style,
+            layer
+        );
+    }
+    xml.push_str("</xournal>\n");
+
+    let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+    encoder.write_all(xml.as_bytes())?;
+    encoder.finish()
+}
+
+fn write_stroke(layer: &mut String, line: &Line, page: &Rect) {
+    let origin = Vector2::new(page.min.x as f64, page.max.y as f64);
+    let points = line.points_from(origin);
+    let color = points[0].color;
+    let _ = write!(
+        layer,
+        "<stroke tool=\"pen\" color=\"{}\" width=\"{}",
+        hex(&color),
+        points[0].width * 2.
+    );
+    if points.iter().any(|pt| pt.width != points[0].width) {
+        for pt in &points[..points.len() - 1] {
+            let _ = write!(layer, " {}", pt.width * 2.);
+        }
+    }
+    layer.push_str("\">");
+    for (i, pt) in points.iter().enumerate() {
+        if i > 0 {
+            layer.push(' ');
+        }
+        let _ = write!(layer, "{} {}", pt.pos.x, -pt.pos.y);
+    }
+    layer.push_str("</stroke>\n");
+}
+
+fn distance(rect: &Rect, pt: Point2<f32>) -> f32 {
+    let dx = (rect.min.x - pt.x).max(pt.x - rect.max.x).max(0.);
+    let dy = (rect.min.y - pt.y).max(pt.y - rect.max.y).max(0.);
+    dx.hypot(dy)
+}
+
+/// `#RRGGBBAA`, `#RRGGBB` or one of the named colors of Xournal.
+fn parse_color(text: &str) -> Option<[f32; 4]> {
+    let rgba = match text {
+        "black" => 0x000000ff,
+        "blue" => 0x3333ccff,
+        "red" => 0xff0000ff,
+        "green" => 0x008000ff,
+        "gray" => 0x808080ff,
+        "lightblue" => 0x00c0ffff,
+        "lightgreen" => 0x00ff00ff,
+        "magenta" => 0xff00ffff,
+        "orange" => 0xff8000ff,
+        "yellow" => 0xffff00ff,
+        "white" => 0xffffffff,
+        _ => {
+            let hex = text.strip_prefix('#')?;
+            match hex.len() {
+                6 => u32::from_str_radix(hex, 16).ok()? << 8 | 0xff,
+                8 => u32::from_str_radix(hex, 16).ok()?,
+                _ => return None,
+            }
+        }
+    };
+    let [r, g, b, a] = u32::to_be_bytes(rgba).map(|c| c as f32 / 255.);
+    Some([
+        point::srgb_to_linear(r),
+        point::srgb_to_linear(g),
+        point::srgb_to_linear(b),
+        a,
+    ])
+}
+
+fn hex(color: &[f32; 4]) -> String {
+    let a = (color[3].clamp(0., 1.) * 255.).round() as u8;
+    format!("{}{:02x}", point::srgb_hex(color), a)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_import() {
+        let text = r##"<?xml version="1.0" standalone="no"?>
+            <xournal creator="Xournal++ 1.1.1" fileversion="4">
+            <page width="100" height="200">
+                <background type="solid" color="#ffffffff" style="lined"/>
+                <layer>
+                    <stroke tool="pen" color="#000000ff" width="2 1 3">0 0 10 20 30 40</stroke>
+                    <stroke tool="highlighter" color="yellow" width="4">5 5 6 6</stroke>
+                </layer>
+            </page>
+            <page width="100" height="50"><layer/></page>
+            </xournal>"##;
+        let (pages, lines) = import_xopp(text.as_bytes(), 0.).unwrap();
+        assert_eq!(pages.len(), 2);
+        assert_eq!(pages[0].background.ruling, Ruling::Lined);
+        assert_eq!(
+            pages[1].rect,
+            Rect::new(Point2::new(0., -260.), Point2::new(100., -210.))
+        );
+        let widths: Vec<_> = lines[0].points().iter().map(|pt| pt.width).collect();
+        assert_eq!(widths, [0.5, 1.5, 1.5]);
+        assert_eq!(lines[0].points()[1].pos, [10., -20., 0.].into());
+        assert_eq!(lines[1].points()[0].color[3], 0.5);
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let mut document = Document::new();
+        let (pages, lines) = import_xopp(
+            br##"<xournal><page width="100" height="200"><layer>
+                <stroke tool="pen" color="#000000ff" width="2 1 3">0 0 10 20 30 40</stroke>
+            </layer></page></xournal>"##,
+            0.,
+        )
+        .unwrap();
+        document.pages = pages;
+        document.push(lines[0].clone());
+        let data = export_xopp(&document).unwrap();
+        let (o_pages, o_lines) = import_xopp(&data, 0.).unwrap();
+        assert_eq!(o_pages, document.pages);
+        assert_eq!(o_lines, lines);
+    }
+}